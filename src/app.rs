@@ -1,38 +1,169 @@
+use cgmath::InnerSpace;
 use eframe::{egui_wgpu, wgpu};
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
 
 use ray_tracing_wgpu::ray_tracer::{self, RayTracer};
 
-const SPEED: f32 = 0.1;
+use crate::bookmark::{self, Bookmark};
+
+// in scene sizes per second
+const FLY_SPEED: f32 = 0.2;
+// scene size the fly speed is kept to at least, for tiny or empty scenes
+const MIN_SCENE_SIZE: f32 = 1.0;
 const SENSITIVITY: f32 = 0.1;
+// duration of smooth transitions between bookmarks in seconds
+const TRANSITION_TIME: f64 = 1.0;
+// frames shown in the performance graph
+const FRAME_TIME_HISTORY: usize = 240;
+// preview while the camera moves, until it has been still for the delay in
+// seconds
+const PREVIEW_SCALE: f32 = 0.25;
+const PREVIEW_MAX_BOUNCE: u32 = 2;
+const PREVIEW_DELAY: f64 = 0.3;
 
 #[cfg(not(target_arch = "wasm32"))]
 const MOUSE_WHEEL_SENSITIVITY: f32 = 1.0;
 #[cfg(target_arch = "wasm32")]
 const MOUSE_WHEEL_SENSITIVITY: f32 = 0.1;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Navigation {
+    Fly,
+    Orbit,
+}
+
+// image sequence being rendered, each frame is saved once its accumulation is
+// finished
+#[cfg(not(target_arch = "wasm32"))]
+struct Sequence {
+    // whether rendering of the current frame has been seen, as the stat may
+    // still be of the previous frame right after it is applied
+    started: bool,
+}
+
+// large image rendered tile by tile, each tile is read back and stitched in
+// once its accumulation is finished
+#[cfg(not(target_arch = "wasm32"))]
+struct TiledRender {
+    image: image::RgbImage,
+    tiles: Vec<ray_tracer::Tile>,
+    tile_idx: usize,
+    // as in Sequence
+    started: bool,
+}
+
 pub struct App {
     param: ray_tracer::Param,
     stat: Arc<Mutex<ray_tracer::Stat>>,
+    // in milliseconds, GPU times if known or else CPU times
+    frame_times: VecDeque<f32>,
     dragging: bool,
+    region_drag_start: Option<egui::Pos2>,
     last_mouse_pos: Option<egui::Pos2>,
+    navigation: Navigation,
+    preview: bool,
+    // camera of the last frame and when it last changed
+    last_camera: ray_tracer::CameraParam,
+    camera_moved_at: f64,
+    orbit_target: cgmath::Vector3<f32>,
+    scene_bbox: (cgmath::Vector3<f32>, cgmath::Vector3<f32>),
+    bookmarks: Vec<Bookmark>,
+    bookmarks_changed: bool,
+    bookmark_name: String,
+    smooth_transition: bool,
+    // cameras to interpolate between and the start time
+    transition: Option<(ray_tracer::CameraParam, ray_tracer::CameraParam, f64)>,
+    timeline: ray_tracer::Timeline,
+    animation_frame: u32,
+    animation_frame_changed: bool,
+    num_objects: usize,
+    object_idx: usize,
+    object_transform: ray_tracer::ObjectTransform,
+    supports_ray_query: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    sequence: Option<Sequence>,
+    #[cfg(not(target_arch = "wasm32"))]
+    sequence_dir: String,
+    // linear EXR frames instead of PNG, for grading and compositing
+    #[cfg(not(target_arch = "wasm32"))]
+    sequence_hdr: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    tiled: Option<TiledRender>,
+    #[cfg(not(target_arch = "wasm32"))]
+    tiled_size: cgmath::Vector2<u32>,
+    #[cfg(not(target_arch = "wasm32"))]
+    tile_size: u32,
+    #[cfg(not(target_arch = "wasm32"))]
+    tiled_path: String,
+    aperture_mask: ray_tracer::ApertureMask,
+    aperture_mask_changed: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    aperture_mask_path: String,
 }
 
 impl App {
     pub fn new<'a>(cc: &'a eframe::CreationContext<'a>) -> Self {
         let state = cc.wgpu_render_state.as_ref().unwrap();
         let ray_traycer = RayTracer::new(&state.device, &state.queue, state.target_format.into());
+        let scene_bbox = ray_traycer.scene_bbox();
+        let num_objects = ray_traycer.num_objects();
+        let supports_ray_query = ray_traycer.supports_ray_query();
         state
             .renderer
             .write()
             .callback_resources
             .insert(ray_traycer);
 
+        let param = ray_tracer::Param::default();
+        let orbit_target = param.camera.target();
+        let last_camera = param.camera.clone();
+
         Self {
-            param: ray_tracer::Param::default(),
+            param,
             stat: Arc::new(Mutex::new(ray_tracer::Stat::default())),
+            frame_times: VecDeque::with_capacity(FRAME_TIME_HISTORY),
             dragging: false,
+            region_drag_start: None,
             last_mouse_pos: None,
+            navigation: Navigation::Fly,
+            preview: false,
+            last_camera,
+            camera_moved_at: f64::NEG_INFINITY,
+            orbit_target,
+            scene_bbox,
+            bookmarks: bookmark::load(cc.storage),
+            bookmarks_changed: false,
+            bookmark_name: String::new(),
+            smooth_transition: true,
+            transition: None,
+            timeline: ray_tracer::Timeline::default(),
+            animation_frame: 0,
+            animation_frame_changed: false,
+            num_objects,
+            object_idx: 0,
+            object_transform: ray_tracer::ObjectTransform::default(),
+            supports_ray_query,
+            #[cfg(not(target_arch = "wasm32"))]
+            sequence: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            sequence_dir: String::from("frames"),
+            #[cfg(not(target_arch = "wasm32"))]
+            sequence_hdr: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            tiled: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            tiled_size: cgmath::vec2(7680, 4320),
+            #[cfg(not(target_arch = "wasm32"))]
+            tile_size: 1024,
+            #[cfg(not(target_arch = "wasm32"))]
+            tiled_path: String::from("render.png"),
+            aperture_mask: ray_tracer::ApertureMask::Heart,
+            aperture_mask_changed: true,
+            #[cfg(not(target_arch = "wasm32"))]
+            aperture_mask_path: String::new(),
         }
     }
 
@@ -43,11 +174,11 @@ impl App {
 
         self.param.display_size = cgmath::Vector2::new(rect.size().x as u32, rect.size().y as u32);
 
-        if response.drag_started() {
+        if response.drag_started_by(egui::PointerButton::Primary) {
             self.dragging = true;
             self.last_mouse_pos = response.interact_pointer_pos();
         }
-        if response.drag_stopped() {
+        if response.drag_stopped_by(egui::PointerButton::Primary) {
             self.dragging = false;
             self.last_mouse_pos = None;
         }
@@ -59,27 +190,167 @@ impl App {
                     self.param.camera.pitch -= delta.y * SENSITIVITY;
 
                     self.param.camera.pitch = self.param.camera.pitch.clamp(-89.9, 89.9);
+
+                    // keep looking at the target from the same distance
+                    if self.navigation == Navigation::Orbit {
+                        let distance = (self.orbit_target - self.param.camera.position).magnitude();
+                        self.param.camera.position =
+                            self.orbit_target - distance * self.param.camera.forward();
+                    }
                 }
                 self.last_mouse_pos = Some(current_pos);
             }
         }
 
+        // the region to render is dragged out with the secondary button
+        if response.drag_started_by(egui::PointerButton::Secondary) {
+            self.region_drag_start = response.interact_pointer_pos();
+        }
+        let region_drag = self
+            .region_drag_start
+            .zip(response.interact_pointer_pos())
+            .map(|(start, end)| egui::Rect::from_two_pos(start, end).intersect(rect));
+        if response.drag_stopped_by(egui::PointerButton::Secondary) {
+            if let Some(region) = region_drag.filter(|region| region.area() > 0.0) {
+                // from points to rendered pixels
+                let render_size = self.param.render_size();
+                let scale = egui::vec2(
+                    render_size.x as f32 / rect.width(),
+                    render_size.y as f32 / rect.height(),
+                );
+                let min = (region.min - rect.min) * scale;
+                let size = region.size() * scale;
+                self.param.region = Some((
+                    cgmath::vec2(min.x as u32, min.y as u32),
+                    cgmath::vec2(size.x.ceil() as u32, size.y.ceil() as u32),
+                ));
+            }
+            self.region_drag_start = None;
+        }
+
+        // render coarsely while the camera moves, accumulating at full quality
+        // only once it stops
+        let time = ui.input(|input| input.time);
+        if self.param.camera != self.last_camera {
+            self.last_camera = self.param.camera.clone();
+            self.camera_moved_at = time;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let offline = self.sequence.is_some() || self.tiled.is_some();
+        #[cfg(target_arch = "wasm32")]
+        let offline = false;
+        let mut param = self.param.clone();
+        // offline frames are rendered from scratch, the reprojected
+        // accumulation already previews the moving camera, and the image
+        // outside of a region would be lost to the resized frame buffer or
+        // saved stale
+        param.reproject &= !offline;
+        if offline {
+            param.region = None;
+        }
+        if self.preview
+            && !offline
+            && !param.reproject
+            && param.region.is_none()
+            && time - self.camera_moved_at < PREVIEW_DELAY
+        {
+            param.render_scale *= PREVIEW_SCALE;
+            param.max_bounce = param.max_bounce.min(PREVIEW_MAX_BOUNCE);
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_secs_f64(PREVIEW_DELAY));
+        }
+
         ui.painter().add(egui_wgpu::Callback::new_paint_callback(
             rect,
             RayTracerCallback {
-                param: self.param.clone(),
+                param,
                 stat: self.stat.clone(),
             },
         ));
+
+        let outline = match region_drag {
+            Some(region) if self.region_drag_start.is_some() => Some(region),
+            _ => self.param.region.map(|_| {
+                let render_size = self.param.render_size();
+                let (offset, size) = self.param.dispatch_region();
+                let scale = egui::vec2(
+                    rect.width() / render_size.x as f32,
+                    rect.height() / render_size.y as f32,
+                );
+                egui::Rect::from_min_size(
+                    rect.min + egui::vec2(offset.x as f32, offset.y as f32) * scale,
+                    egui::vec2(size.x as f32, size.y as f32) * scale,
+                )
+            }),
+        };
+        if let Some(outline) = outline {
+            ui.painter().rect_stroke(
+                outline,
+                0.0,
+                egui::Stroke::new(1.0, ui.visuals().warn_fg_color),
+                egui::StrokeKind::Outside,
+            );
+        }
+    }
+
+    // rolling graph of the frame times, scaled to the slowest frame
+    fn performance_graph(ui: &mut egui::Ui, frame_times: &VecDeque<f32>) {
+        let (rect, _) =
+            ui.allocate_exact_size(egui::vec2(ui.available_width(), 60.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, ui.visuals().faint_bg_color);
+
+        let max_time = frame_times.iter().copied().fold(1.0, f32::max);
+        let points = frame_times
+            .iter()
+            .enumerate()
+            .map(|(i, time)| {
+                let x = i as f32 / (FRAME_TIME_HISTORY - 1) as f32;
+                egui::pos2(
+                    rect.left() + x * rect.width(),
+                    rect.bottom() - time / max_time * rect.height(),
+                )
+            })
+            .collect::<Vec<_>>();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.5, ui.visuals().widgets.active.bg_fill),
+        ));
+        painter.text(
+            rect.left_top() + egui::vec2(4.0, 2.0),
+            egui::Align2::LEFT_TOP,
+            format!("{max_time:.1} ms"),
+            egui::FontId::monospace(10.0),
+            ui.visuals().text_color(),
+        );
     }
 }
 
 impl eframe::App for App {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         let stat = self.stat.lock().unwrap();
 
         if stat.is_rendering {
             ctx.request_repaint();
+
+            let time = match stat.gpu_time {
+                Some(gpu_time) => gpu_time.compute + gpu_time.render.unwrap_or(0.0),
+                None => stat.frame_time,
+            };
+            if self.frame_times.len() == FRAME_TIME_HISTORY {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(time);
+        }
+
+        if let Some((from, to, start)) = &self.transition {
+            let t = ((ctx.input(|input| input.time) - start) / TRANSITION_TIME).min(1.0) as f32;
+            let t = t * t * (3.0 - 2.0 * t);
+            self.param.camera = from.lerp(to, t);
+            if t >= 1.0 {
+                self.transition = None;
+            }
+            ctx.request_repaint();
         }
 
         egui::Window::new("Control Panel")
@@ -92,7 +363,9 @@ impl eframe::App for App {
                 ui.set_width(panel_width);
                 ui.add_space(4.0);
 
-                let status = if stat.is_rendering {
+                let status = if stat.error.is_some() {
+                    String::from("Error")
+                } else if stat.is_rendering {
                     format!("Rendering ({:.1}s elapsed)", stat.time_spent.as_secs_f32())
                 } else {
                     format!("Finished ({:.1}s total)", stat.time_spent.as_secs_f32())
@@ -102,20 +375,87 @@ impl eframe::App for App {
                     .fill(ui.visuals().extreme_bg_color)
                     .stroke(egui::Stroke::new(1.0, ui.visuals().widgets.active.bg_fill))
                     .show(ui, |ui| {
-                        ui.add(
-                            egui::ProgressBar::new(
-                                stat.frame_counter as f32 / self.param.max_sample as f32,
-                            )
-                            .text(format!(
-                                "Frame: {}/{}",
-                                stat.frame_counter, self.param.max_sample
-                            )),
-                        );
+                        if let Some(error) = &stat.error {
+                            ui.colored_label(ui.visuals().error_fg_color, error);
+                        }
 
+                        // adaptive sampling finishes once every pixel converges,
+                        // which may well be before the last frame
+                        let render_size = self.param.render_size();
+                        let (_, dispatch_size) = self.param.dispatch_region();
+                        let num_pixel = dispatch_size.x * dispatch_size.y;
+                        let converged = match stat.active_pixels {
+                            Some(active) if self.param.noise_threshold > 0.0 => {
+                                1.0 - active as f32 / num_pixel.max(1) as f32
+                            }
+                            _ => 0.0,
+                        };
+                        let progress = (stat.frame_counter as f32 / self.param.max_sample as f32)
+                            .max(converged);
+                        ui.add(egui::ProgressBar::new(progress).text(format!(
+                            "Samples: {}/{}",
+                            stat.frame_counter, self.param.max_sample
+                        )));
+                        if let Some(noise) = stat.noise {
+                            ui.label(format!("Noise: {:.2}%", noise * 100.0));
+                        }
+                        if let (Some(active), true) =
+                            (stat.active_pixels, self.param.noise_threshold > 0.0)
+                        {
+                            ui.label(format!("Active Pixels: {active}/{num_pixel}"));
+                        }
+
+                        ui.label(format!(
+                            "Screen Size: {}x{}, Render Size: {}x{}",
+                            self.param.display_size.x,
+                            self.param.display_size.y,
+                            render_size.x,
+                            render_size.y
+                        ));
+                        ui.horizontal(|ui| match self.param.dispatch_region() {
+                            (offset, size) if self.param.region.is_some() => {
+                                ui.label(format!(
+                                    "Region: {}x{} at ({}, {})",
+                                    size.x, size.y, offset.x, offset.y
+                                ));
+                                if ui.small_button("Clear").clicked() {
+                                    self.param.region = None;
+                                }
+                            }
+                            _ => {
+                                ui.label("Region: right drag on the image to select");
+                            }
+                        });
+                    });
+
+                ui.label(egui::RichText::new("Performance").heading().strong());
+                egui::Frame::group(ui.style())
+                    .fill(ui.visuals().extreme_bg_color)
+                    .stroke(egui::Stroke::new(1.0, ui.visuals().widgets.active.bg_fill))
+                    .show(ui, |ui| {
+                        ui.set_width(panel_width - 16.0);
+
+                        match stat.gpu_time {
+                            Some(gpu_time) => {
+                                let render = gpu_time.render.map_or(String::new(), |render| {
+                                    format!(", Render: {render:.2} ms")
+                                });
+                                ui.label(format!(
+                                    "GPU Compute: {:.2} ms{render}",
+                                    gpu_time.compute
+                                ));
+                            }
+                            None => {
+                                ui.label(format!("CPU Frame: {:.2} ms", stat.frame_time));
+                            }
+                        }
                         ui.label(format!(
-                            "Screen Size: {}x{}",
-                            self.param.display_size.x, self.param.display_size.y
+                            "{:.1} MSamples/s, {:.1} MRays/s, {} Samples per Dispatch",
+                            stat.samples_per_second / 1e6,
+                            stat.rays_per_second / 1e6,
+                            stat.samples_per_dispatch
                         ));
+                        Self::performance_graph(ui, &self.frame_times);
                     });
 
                 ui.label(egui::RichText::new("Sampling").heading().strong());
@@ -129,10 +469,124 @@ impl eframe::App for App {
                             ui.add_sized([label_width, 0.0], egui::Label::new("Samples per Pixel"));
                             ui.add(egui::Slider::new(&mut self.param.max_sample, 1..=4096));
                         });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Noise Threshold"));
+                            ui.add(
+                                egui::Slider::new(&mut self.param.noise_threshold, 0.0..=0.2)
+                                    .custom_formatter(|value, _| match value {
+                                        0.0 => "Off".to_string(),
+                                        _ => format!("{:.1}%", value * 100.0),
+                                    }),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Time Budget"));
+                            ui.add(
+                                egui::Slider::new(&mut self.param.time_budget, 0.0..=600.0)
+                                    .custom_formatter(|value, _| match value {
+                                        0.0 => "Unlimited".to_string(),
+                                        _ => format!("{value:.0} s"),
+                                    }),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Target Frame Time"));
+                            ui.add(
+                                egui::Slider::new(&mut self.param.target_frame_time, 0.0..=100.0)
+                                    .custom_formatter(|value, _| match value {
+                                        0.0 => "Off".to_string(),
+                                        _ => format!("{value:.0} ms"),
+                                    }),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized(
+                                [label_width, 0.0],
+                                egui::Label::new("Samples per Dispatch"),
+                            );
+                            ui.add_enabled(
+                                self.param.target_frame_time == 0.0,
+                                egui::Slider::new(&mut self.param.samples_per_dispatch, 1..=64),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Render Scale"));
+                            ui.add(
+                                egui::Slider::new(&mut self.param.render_scale, 0.25..=2.0)
+                                    .suffix("×"),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Display Filter"));
+                            ui.radio_value(
+                                &mut self.param.display_filter,
+                                ray_tracer::DisplayFilter::Nearest,
+                                "Nearest",
+                            );
+                            ui.radio_value(
+                                &mut self.param.display_filter,
+                                ray_tracer::DisplayFilter::Bilinear,
+                                "Bilinear",
+                            );
+                            ui.radio_value(
+                                &mut self.param.display_filter,
+                                ray_tracer::DisplayFilter::Bicubic,
+                                "Bicubic",
+                            );
+                        });
                         ui.horizontal(|ui| {
                             ui.add_sized([label_width, 0.0], egui::Label::new("Max Bounces"));
                             ui.add(egui::Slider::new(&mut self.param.max_bounce, 1..=32));
                         });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Filter"));
+                            let filter = self.param.filter;
+                            egui::ComboBox::from_id_salt("filter")
+                                .selected_text(format!("{:?}", self.param.filter))
+                                .show_ui(ui, |ui| {
+                                    for filter in [
+                                        ray_tracer::Filter::Box,
+                                        ray_tracer::Filter::Gaussian,
+                                        ray_tracer::Filter::Mitchell,
+                                        ray_tracer::Filter::BlackmanHarris,
+                                        ray_tracer::Filter::Lanczos,
+                                    ] {
+                                        ui.selectable_value(
+                                            &mut self.param.filter,
+                                            filter,
+                                            format!("{filter:?}"),
+                                        );
+                                    }
+                                });
+                            if self.param.filter != filter {
+                                self.param.filter_radius = self.param.filter.default_radius();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Filter Radius"));
+                            ui.add(
+                                egui::Slider::new(&mut self.param.filter_radius, 0.5..=4.0)
+                                    .suffix(" px"),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Sampler"));
+                            ui.radio_value(
+                                &mut self.param.sampler,
+                                ray_tracer::Sampler::Pcg,
+                                "PCG",
+                            );
+                            ui.radio_value(
+                                &mut self.param.sampler,
+                                ray_tracer::Sampler::Sobol,
+                                "Sobol",
+                            );
+                            ui.radio_value(
+                                &mut self.param.sampler,
+                                ray_tracer::Sampler::BlueNoise,
+                                "Blue Noise",
+                            );
+                        });
                     });
 
                 ui.label(egui::RichText::new("Camera").heading().strong());
@@ -143,15 +597,204 @@ impl eframe::App for App {
                         ui.set_width(panel_width - 16.0);
 
                         ui.horizontal(|ui| {
-                            ui.add_sized([label_width, 0.0], egui::Label::new("FOV"));
-                            ui.add(egui::Slider::new(&mut self.param.camera.fov, 10.0..=120.0));
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Projection"));
+                            egui::ComboBox::from_id_salt("projection")
+                                .selected_text(format!("{:?}", self.param.camera.projection))
+                                .show_ui(ui, |ui| {
+                                    for projection in [
+                                        ray_tracer::Projection::Perspective,
+                                        ray_tracer::Projection::Orthographic,
+                                        ray_tracer::Projection::Fisheye,
+                                        ray_tracer::Projection::Equirectangular,
+                                        ray_tracer::Projection::Cylindrical,
+                                    ] {
+                                        ui.selectable_value(
+                                            &mut self.param.camera.projection,
+                                            projection,
+                                            format!("{projection:?}"),
+                                        );
+                                    }
+                                });
                         });
                         ui.horizontal(|ui| {
-                            ui.add_sized([label_width, 0.0], egui::Label::new("Aperture"));
-                            ui.add(egui::Slider::new(
-                                &mut self.param.camera.aperture,
-                                0.0..=1.0,
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Physical Camera"));
+                            ui.checkbox(&mut self.param.camera.use_physical, "");
+                        });
+                        if self.param.camera.use_physical {
+                            let physical = &mut self.param.camera.physical;
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("Sensor Width"));
+                                ui.add(
+                                    egui::Slider::new(&mut physical.sensor_width, 4.0..=70.0)
+                                        .suffix(" mm"),
+                                );
+                            });
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("Focal Length"));
+                                ui.add(
+                                    egui::Slider::new(&mut physical.focal_length, 8.0..=800.0)
+                                        .logarithmic(true)
+                                        .suffix(" mm"),
+                                );
+                            });
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("F-Stop"));
+                                ui.add(
+                                    egui::Slider::new(&mut physical.f_stop, 1.0..=32.0)
+                                        .logarithmic(true)
+                                        .prefix("f/"),
+                                );
+                            });
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("Shutter Speed"));
+                                ui.add(
+                                    egui::Slider::new(
+                                        &mut physical.shutter_speed,
+                                        1.0 / 8000.0..=30.0,
+                                    )
+                                    .logarithmic(true)
+                                    .suffix(" s"),
+                                );
+                            });
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("ISO"));
+                                ui.add(
+                                    egui::Slider::new(&mut physical.iso, 50.0..=25600.0)
+                                        .logarithmic(true),
+                                );
+                            });
+                            let aspect_ratio =
+                                self.param.display_size.x as f32 / self.param.display_size.y as f32;
+                            ui.label(format!(
+                                "FOV: {:.1}°, EV100: {:.1}",
+                                physical.fov(aspect_ratio),
+                                physical.ev100()
                             ));
+                        } else {
+                            let max_fov = self.param.camera.projection.max_fov();
+                            self.param.camera.fov = self.param.camera.fov.min(max_fov);
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("FOV"));
+                                ui.add(egui::Slider::new(
+                                    &mut self.param.camera.fov,
+                                    10.0..=max_fov,
+                                ));
+                            });
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("Aperture"));
+                                ui.add(egui::Slider::new(
+                                    &mut self.param.camera.aperture,
+                                    0.0..=1.0,
+                                ));
+                            });
+                        }
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Aperture Shape"));
+                            ui.radio_value(
+                                &mut self.param.camera.aperture_shape,
+                                ray_tracer::ApertureShape::Circle,
+                                "Circle",
+                            );
+                            ui.radio_value(
+                                &mut self.param.camera.aperture_shape,
+                                ray_tracer::ApertureShape::Polygon,
+                                "Polygon",
+                            );
+                            ui.radio_value(
+                                &mut self.param.camera.aperture_shape,
+                                ray_tracer::ApertureShape::Mask,
+                                "Mask",
+                            );
+                        });
+                        match self.param.camera.aperture_shape {
+                            ray_tracer::ApertureShape::Polygon => {
+                                ui.horizontal(|ui| {
+                                    ui.add_sized([label_width, 0.0], egui::Label::new("Blades"));
+                                    ui.add(egui::Slider::new(
+                                        &mut self.param.camera.aperture_blades,
+                                        3..=12,
+                                    ));
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.add_sized(
+                                        [label_width, 0.0],
+                                        egui::Label::new("Blade Rotation"),
+                                    );
+                                    ui.add(egui::Slider::new(
+                                        &mut self.param.camera.aperture_rotation,
+                                        -180.0..=180.0,
+                                    ));
+                                });
+                            }
+                            ray_tracer::ApertureShape::Mask => {
+                                ui.horizontal(|ui| {
+                                    ui.add_sized([label_width, 0.0], egui::Label::new("Mask"));
+                                    if ui
+                                        .radio(
+                                            matches!(
+                                                self.aperture_mask,
+                                                ray_tracer::ApertureMask::Heart
+                                            ),
+                                            "Heart",
+                                        )
+                                        .clicked()
+                                    {
+                                        self.aperture_mask = ray_tracer::ApertureMask::Heart;
+                                        self.aperture_mask_changed = true;
+                                    }
+                                    if ui
+                                        .radio(
+                                            matches!(
+                                                self.aperture_mask,
+                                                ray_tracer::ApertureMask::Star
+                                            ),
+                                            "Star",
+                                        )
+                                        .clicked()
+                                    {
+                                        self.aperture_mask = ray_tracer::ApertureMask::Star;
+                                        self.aperture_mask_changed = true;
+                                    }
+                                    ui.add_enabled(
+                                        false,
+                                        egui::RadioButton::new(
+                                            matches!(
+                                                self.aperture_mask,
+                                                ray_tracer::ApertureMask::Image(_)
+                                            ),
+                                            "Image",
+                                        ),
+                                    );
+                                });
+                                #[cfg(not(target_arch = "wasm32"))]
+                                ui.horizontal(|ui| {
+                                    ui.add_sized(
+                                        [label_width, 0.0],
+                                        egui::Label::new("Mask Image"),
+                                    );
+                                    ui.text_edit_singleline(&mut self.aperture_mask_path);
+                                    if ui.button("Load").clicked() {
+                                        match image::open(&self.aperture_mask_path) {
+                                            Ok(image) => {
+                                                self.aperture_mask =
+                                                    ray_tracer::ApertureMask::Image(
+                                                        image.to_luma8(),
+                                                    );
+                                                self.aperture_mask_changed = true;
+                                            }
+                                            Err(err) => log::error!(
+                                                "Failed to load aperture mask {}: {err}",
+                                                self.aperture_mask_path
+                                            ),
+                                        }
+                                    }
+                                });
+                            }
+                            ray_tracer::ApertureShape::Circle => {}
+                        }
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Cat Eye"));
+                            ui.add(egui::Slider::new(&mut self.param.camera.cat_eye, 0.0..=1.0));
                         });
                         ui.horizontal(|ui| {
                             ui.add_sized([label_width, 0.0], egui::Label::new("Focus Distance"));
@@ -160,6 +803,20 @@ impl eframe::App for App {
                                 0.0..=100.0,
                             ));
                         });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Shutter Open"));
+                            ui.add(egui::Slider::new(
+                                &mut self.param.camera.shutter_open,
+                                0.0..=1.0,
+                            ));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Shutter Close"));
+                            ui.add(egui::Slider::new(
+                                &mut self.param.camera.shutter_close,
+                                self.param.camera.shutter_open..=1.0,
+                            ));
+                        });
                         ui.horizontal(|ui| {
                             ui.add_sized([label_width, 0.0], egui::Label::new("Pitch"));
                             ui.add(egui::Slider::new(
@@ -174,6 +831,13 @@ impl eframe::App for App {
                                 -180.0..=180.0,
                             ));
                         });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Roll"));
+                            ui.add(egui::Slider::new(
+                                &mut self.param.camera.roll,
+                                -180.0..=180.0,
+                            ));
+                        });
                         ui.horizontal(|ui| {
                             ui.label("Position");
                             ui.add(
@@ -192,6 +856,348 @@ impl eframe::App for App {
                                     .prefix("z: "),
                             );
                         });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Navigation"));
+                            ui.radio_value(&mut self.navigation, Navigation::Fly, "Fly");
+                            if ui
+                                .radio_value(&mut self.navigation, Navigation::Orbit, "Orbit")
+                                .clicked()
+                            {
+                                self.orbit_target = self.param.camera.target();
+                            }
+                            if ui.button("Frame All").clicked() {
+                                let (min, max) = self.scene_bbox;
+                                let aspect_ratio = self.param.display_size.x as f32
+                                    / self.param.display_size.y as f32;
+                                self.param.camera.frame(min, max, aspect_ratio);
+                                self.orbit_target = (min + max) / 2.0;
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Moving Preview"));
+                            ui.checkbox(&mut self.preview, "").on_hover_text(
+                                "Render at low resolution and few bounces while the camera moves",
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Reprojection"));
+                            ui.checkbox(&mut self.param.reproject, "")
+                                .on_hover_text("Carry the accumulated samples over camera moves");
+                        });
+                    });
+
+                ui.label(egui::RichText::new("Bookmarks").heading().strong());
+                egui::Frame::group(ui.style())
+                    .fill(ui.visuals().extreme_bg_color)
+                    .stroke(egui::Stroke::new(1.0, ui.visuals().widgets.active.bg_fill))
+                    .show(ui, |ui| {
+                        ui.set_width(panel_width - 16.0);
+
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.bookmark_name)
+                                    .hint_text("Name")
+                                    .desired_width(label_width * 2.0),
+                            );
+                            if ui.button("Add").clicked() {
+                                let name = if self.bookmark_name.is_empty() {
+                                    format!("View {}", self.bookmarks.len() + 1)
+                                } else {
+                                    std::mem::take(&mut self.bookmark_name)
+                                };
+                                self.bookmarks.push(Bookmark {
+                                    name,
+                                    camera: self.param.camera.clone(),
+                                });
+                                self.bookmarks_changed = true;
+                            }
+                        });
+
+                        let mut removed = None;
+                        for (i, bookmark) in self.bookmarks.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.button(&bookmark.name).clicked() {
+                                    if self.smooth_transition {
+                                        self.transition = Some((
+                                            self.param.camera.clone(),
+                                            bookmark.camera.clone(),
+                                            ui.input(|input| input.time),
+                                        ));
+                                    } else {
+                                        self.param.camera = bookmark.camera.clone();
+                                    }
+                                    self.orbit_target = bookmark.camera.target();
+                                }
+                                if ui.small_button("Remove").clicked() {
+                                    removed = Some(i);
+                                }
+                            });
+                        }
+                        if let Some(i) = removed {
+                            self.bookmarks.remove(i);
+                            self.bookmarks_changed = true;
+                        }
+
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Smooth"));
+                            ui.checkbox(&mut self.smooth_transition, "");
+                        });
+                    });
+
+                ui.label(egui::RichText::new("Animation").heading().strong());
+                egui::Frame::group(ui.style())
+                    .fill(ui.visuals().extreme_bg_color)
+                    .stroke(egui::Stroke::new(1.0, ui.visuals().widgets.active.bg_fill))
+                    .show(ui, |ui| {
+                        ui.set_width(panel_width - 16.0);
+
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Frames"));
+                            ui.add(
+                                egui::DragValue::new(&mut self.timeline.num_frame).range(1..=10000),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Interpolation"));
+                            ui.radio_value(
+                                &mut self.timeline.interpolation,
+                                ray_tracer::Interpolation::Linear,
+                                "Linear",
+                            );
+                            ui.radio_value(
+                                &mut self.timeline.interpolation,
+                                ray_tracer::Interpolation::CatmullRom,
+                                "Catmull-Rom",
+                            );
+                        });
+                        self.animation_frame =
+                            self.animation_frame.min(self.timeline.num_frame - 1);
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Frame"));
+                            if ui
+                                .add(egui::Slider::new(
+                                    &mut self.animation_frame,
+                                    0..=self.timeline.num_frame - 1,
+                                ))
+                                .changed()
+                            {
+                                self.animation_frame_changed = true;
+                            }
+                        });
+
+                        if ui.button("Add Camera Keyframe").clicked() {
+                            self.timeline
+                                .add_camera(self.animation_frame as f32, self.param.camera.clone());
+                        }
+                        let mut removed = None;
+                        for (i, (time, _)) in self.timeline.cameras.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Camera at frame {time}"));
+                                if ui.small_button("Remove").clicked() {
+                                    removed = Some(i);
+                                }
+                            });
+                        }
+                        if let Some(i) = removed {
+                            self.timeline.cameras.remove(i);
+                        }
+
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Object"));
+                            ui.add(
+                                egui::DragValue::new(&mut self.object_idx)
+                                    .range(0..=self.num_objects - 1),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Translation");
+                            let translation = &mut self.object_transform.translation;
+                            ui.add(
+                                egui::DragValue::new(&mut translation.x)
+                                    .speed(0.1)
+                                    .prefix("x: "),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut translation.y)
+                                    .speed(0.1)
+                                    .prefix("y: "),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut translation.z)
+                                    .speed(0.1)
+                                    .prefix("z: "),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Rotation");
+                            let rotation = &mut self.object_transform.rotation;
+                            ui.add(egui::DragValue::new(&mut rotation.x).prefix("x: "));
+                            ui.add(egui::DragValue::new(&mut rotation.y).prefix("y: "));
+                            ui.add(egui::DragValue::new(&mut rotation.z).prefix("z: "));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Scale"));
+                            ui.add(
+                                egui::DragValue::new(&mut self.object_transform.scale)
+                                    .speed(0.01)
+                                    .range(0.01..=100.0),
+                            );
+                        });
+                        if ui.button("Add Object Keyframe").clicked() {
+                            self.timeline.add_object(
+                                self.object_idx,
+                                self.animation_frame as f32,
+                                self.object_transform,
+                            );
+                            self.animation_frame_changed = true;
+                        }
+                        let mut removed = None;
+                        for (i, track) in self.timeline.objects.iter().enumerate() {
+                            for (j, (time, _)) in track.keyframes.iter().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!(
+                                        "Object {} at frame {time}",
+                                        track.object_idx
+                                    ));
+                                    if ui.small_button("Remove").clicked() {
+                                        removed = Some((i, j));
+                                    }
+                                });
+                            }
+                        }
+                        if let Some((i, j)) = removed {
+                            self.timeline.objects[i].keyframes.remove(j);
+                            if self.timeline.objects[i].keyframes.is_empty() {
+                                self.timeline.objects.remove(i);
+                            }
+                            self.animation_frame_changed = true;
+                        }
+
+                        #[cfg(not(target_arch = "wasm32"))]
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.sequence_dir)
+                                    .hint_text("Output directory")
+                                    .desired_width(label_width * 1.5),
+                            );
+                            ui.add_enabled(
+                                self.sequence.is_none(),
+                                egui::Checkbox::new(&mut self.sequence_hdr, "EXR"),
+                            );
+                            match self.sequence {
+                                Some(_) => {
+                                    ui.label(format!(
+                                        "Frame {}/{}",
+                                        self.animation_frame + 1,
+                                        self.timeline.num_frame
+                                    ));
+                                    if ui.button("Cancel").clicked() {
+                                        self.sequence = None;
+                                    }
+                                }
+                                None => {
+                                    if ui.button("Render Sequence").clicked() {
+                                        match std::fs::create_dir_all(&self.sequence_dir) {
+                                            Ok(()) => {
+                                                self.sequence = Some(Sequence { started: false });
+                                                self.animation_frame = 0;
+                                                self.animation_frame_changed = true;
+                                            }
+                                            Err(err) => log::error!(
+                                                "Failed to create output directory {}: {err}",
+                                                self.sequence_dir
+                                            ),
+                                        }
+                                    }
+                                }
+                            }
+                        });
+                    });
+
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.label(egui::RichText::new("Tiled Render").heading().strong());
+                #[cfg(not(target_arch = "wasm32"))]
+                egui::Frame::group(ui.style())
+                    .fill(ui.visuals().extreme_bg_color)
+                    .stroke(egui::Stroke::new(1.0, ui.visuals().widgets.active.bg_fill))
+                    .show(ui, |ui| {
+                        ui.set_width(panel_width - 16.0);
+
+                        ui.add_enabled_ui(self.tiled.is_none(), |ui| {
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("Image Size"));
+                                ui.add(
+                                    egui::DragValue::new(&mut self.tiled_size.x)
+                                        .range(1..=65536)
+                                        .suffix(" px"),
+                                );
+                                ui.label("×");
+                                ui.add(
+                                    egui::DragValue::new(&mut self.tiled_size.y)
+                                        .range(1..=65536)
+                                        .suffix(" px"),
+                                );
+                            });
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("Tile Size"));
+                                ui.add(
+                                    egui::Slider::new(&mut self.tile_size, 128..=4096)
+                                        .logarithmic(true)
+                                        .suffix(" px"),
+                                );
+                            });
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.tiled_path)
+                                    .hint_text("Output file")
+                                    .desired_width(label_width * 1.5),
+                            );
+                            match &self.tiled {
+                                Some(_) => {
+                                    if ui.button("Cancel").clicked() {
+                                        self.tiled = None;
+                                        self.param.tile = None;
+                                    }
+                                }
+                                None => {
+                                    if ui.button("Render Tiles").clicked() {
+                                        let tiles =
+                                            ray_tracer::Tile::grid(self.tiled_size, self.tile_size);
+                                        self.param.tile = tiles.first().copied();
+                                        self.tiled = Some(TiledRender {
+                                            image: image::RgbImage::new(
+                                                self.tiled_size.x,
+                                                self.tiled_size.y,
+                                            ),
+                                            tiles,
+                                            tile_idx: 0,
+                                            started: false,
+                                        });
+                                    }
+                                }
+                            }
+                        });
+                        if let Some(tiled) = &self.tiled {
+                            let tile_progress = if tiled.started {
+                                stat.frame_counter as f32 / self.param.max_sample as f32
+                            } else {
+                                0.0
+                            };
+                            ui.add(
+                                egui::ProgressBar::new(
+                                    (tiled.tile_idx as f32 + tile_progress)
+                                        / tiled.tiles.len() as f32,
+                                )
+                                .text(format!(
+                                    "Tile {}/{}: {:.0}%",
+                                    tiled.tile_idx + 1,
+                                    tiled.tiles.len(),
+                                    tile_progress * 100.0
+                                )),
+                            );
+                        }
                     });
 
                 ui.label(egui::RichText::new("Algorithm").heading().strong());
@@ -201,6 +1207,19 @@ impl eframe::App for App {
                     .show(ui, |ui| {
                         ui.set_width(panel_width - 16.0);
 
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Kernel"));
+                            ui.radio_value(
+                                &mut self.param.kernel,
+                                ray_tracer::Kernel::Megakernel,
+                                "Megakernel",
+                            );
+                            ui.radio_value(
+                                &mut self.param.kernel,
+                                ray_tracer::Kernel::Wavefront,
+                                "Wavefront",
+                            );
+                        });
                         ui.horizontal(|ui| {
                             ui.add_sized([label_width, 0.0], egui::Label::new("Hit"));
                             ui.radio_value(
@@ -213,7 +1232,59 @@ impl eframe::App for App {
                                 ray_tracer::HitAlgorithm::BVH,
                                 "BVH",
                             );
+                            ui.radio_value(
+                                &mut self.param.hit_algorithm,
+                                ray_tracer::HitAlgorithm::RayQuery,
+                                "Ray Query",
+                            )
+                            .on_hover_text(
+                                if self.supports_ray_query {
+                                    "Hardware ray tracing of the triangles"
+                                } else {
+                                    "Not supported by the device, falls back to the BVH"
+                                },
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("BVH Builder"));
+                            ui.radio_value(
+                                &mut self.param.bvh_builder,
+                                ray_tracer::BvhBuilder::Cpu,
+                                "CPU",
+                            );
+                            ui.radio_value(
+                                &mut self.param.bvh_builder,
+                                ray_tracer::BvhBuilder::Lbvh,
+                                "GPU (LBVH)",
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_sized([label_width, 0.0], egui::Label::new("Refit BVH"));
+                            ui.checkbox(&mut self.param.bvh_refit, "").on_hover_text(
+                                "Refit the BVH to moved objects instead of rebuilding it",
+                            );
                         });
+                        if self.param.bvh_refit {
+                            ui.horizontal(|ui| {
+                                ui.add_sized([label_width, 0.0], egui::Label::new("Rebuild Ratio"));
+                                // ratios below 1 would rebuild on every refit
+                                let mut rebuild = self.param.bvh_rebuild_ratio > 0.0;
+                                ui.checkbox(&mut rebuild, "")
+                                    .on_hover_text("Rebuild once the SAH cost grows by the ratio");
+                                if !rebuild {
+                                    self.param.bvh_rebuild_ratio = 0.0;
+                                    return;
+                                }
+                                if self.param.bvh_rebuild_ratio == 0.0 {
+                                    self.param.bvh_rebuild_ratio =
+                                        ray_tracer::Param::default().bvh_rebuild_ratio;
+                                }
+                                ui.add(egui::Slider::new(
+                                    &mut self.param.bvh_rebuild_ratio,
+                                    1.0..=4.0,
+                                ));
+                            });
+                        }
                         ui.horizontal(|ui| {
                             ui.add_sized([label_width, 0.0], egui::Label::new("Shading"));
                             ui.radio_value(
@@ -230,43 +1301,173 @@ impl eframe::App for App {
                     });
             });
 
+        // keys typed into a text field are not for moving the camera
+        let wants_keyboard_input = ctx.wants_keyboard_input();
         ctx.input(|input| {
+            let (min, max) = self.scene_bbox;
+            let speed = FLY_SPEED * (max - min).magnitude().max(MIN_SCENE_SIZE) * input.stable_dt;
             let yaw = self.param.camera.yaw;
             let forward =
                 -cgmath::Matrix3::from_angle_y(cgmath::Deg(yaw)) * cgmath::Vector3::unit_z();
             let right = cgmath::Matrix3::from_angle_y(cgmath::Deg(yaw)) * cgmath::Vector3::unit_x();
+            let up = cgmath::Vector3::unit_y();
 
-            for event in &input.events {
-                match event {
-                    egui::Event::Key {
-                        key, pressed: true, ..
-                    } => match key {
-                        egui::Key::W => self.param.camera.position += SPEED * forward,
-                        egui::Key::A => self.param.camera.position -= SPEED * right,
-                        egui::Key::S => self.param.camera.position -= SPEED * forward,
-                        egui::Key::D => self.param.camera.position += SPEED * right,
-                        egui::Key::Space => self.param.camera.position.y += SPEED,
-                        egui::Key::ArrowUp => self.param.camera.position += SPEED * forward,
-                        egui::Key::ArrowDown => self.param.camera.position -= SPEED * forward,
-                        egui::Key::ArrowLeft => self.param.camera.position -= SPEED * right,
-                        egui::Key::ArrowRight => self.param.camera.position += SPEED * right,
-                        _ => {}
-                    },
-                    egui::Event::MouseWheel { delta, .. } => {
-                        self.param.camera.fov -= delta.y * MOUSE_WHEEL_SENSITIVITY;
-                        self.param.camera.fov = self.param.camera.fov.clamp(10.0, 120.0);
+            let mut offset = cgmath::Vector3::new(0.0, 0.0, 0.0);
+            if !wants_keyboard_input {
+                for (key, direction) in [
+                    (egui::Key::W, forward),
+                    (egui::Key::A, -right),
+                    (egui::Key::S, -forward),
+                    (egui::Key::D, right),
+                    (egui::Key::Space, up),
+                    (egui::Key::ArrowUp, forward),
+                    (egui::Key::ArrowDown, -forward),
+                    (egui::Key::ArrowLeft, -right),
+                    (egui::Key::ArrowRight, right),
+                ] {
+                    if input.key_down(key) {
+                        offset += direction;
                     }
-                    _ => {}
+                }
+                if input.modifiers.shift {
+                    offset -= up;
                 }
             }
 
-            if input.modifiers.shift {
-                self.param.camera.position.y -= SPEED;
+            // orbiting pans the target along with the camera
+            if offset != cgmath::Vector3::new(0.0, 0.0, 0.0) {
+                self.param.camera.position += speed * offset;
+                self.orbit_target += speed * offset;
+            }
+
+            for event in &input.events {
+                if let egui::Event::MouseWheel { delta, .. } = event {
+                    if self.param.camera.use_physical {
+                        let physical = &mut self.param.camera.physical;
+                        physical.focal_length += delta.y * MOUSE_WHEEL_SENSITIVITY;
+                        physical.focal_length = physical.focal_length.clamp(8.0, 800.0);
+                    } else {
+                        self.param.camera.fov -= delta.y * MOUSE_WHEEL_SENSITIVITY;
+                        self.param.camera.fov = self
+                            .param
+                            .camera
+                            .fov
+                            .clamp(10.0, self.param.camera.projection.max_fov());
+                    }
+                }
             }
         });
 
         drop(stat);
 
+        if self.aperture_mask_changed {
+            let state = frame.wgpu_render_state().unwrap();
+            let mut renderer = state.renderer.write();
+            let ray_tracer: &mut RayTracer = renderer.callback_resources.get_mut().unwrap();
+            ray_tracer.set_aperture_mask(&state.device, &state.queue, &self.aperture_mask);
+            self.aperture_mask_changed = false;
+        }
+
+        if self.bookmarks_changed {
+            bookmark::save(frame.storage_mut(), &self.bookmarks);
+            self.bookmarks_changed = false;
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(sequence) = &mut self.sequence {
+            ctx.request_repaint();
+
+            let is_rendering = self.stat.lock().unwrap().is_rendering;
+            if is_rendering {
+                sequence.started = true;
+            } else if sequence.started && !self.animation_frame_changed {
+                let state = frame.wgpu_render_state().unwrap();
+                let renderer = state.renderer.read();
+                let ray_tracer: &RayTracer = renderer.callback_resources.get().unwrap();
+                let extension = if self.sequence_hdr { "exr" } else { "png" };
+                let path = std::path::Path::new(&self.sequence_dir)
+                    .join(format!("frame_{:04}.{extension}", self.animation_frame));
+                let result = if self.sequence_hdr {
+                    ray_tracer
+                        .read_image_hdr(&state.device, &state.queue)
+                        .save(&path)
+                } else {
+                    ray_tracer
+                        .read_image(&state.device, &state.queue)
+                        .save(&path)
+                };
+                if let Err(err) = result {
+                    log::error!("Failed to save {}: {err}", path.display());
+                    self.sequence = None;
+                } else if self.animation_frame + 1 < self.timeline.num_frame {
+                    sequence.started = false;
+                    self.animation_frame += 1;
+                    self.animation_frame_changed = true;
+                } else {
+                    self.sequence = None;
+                }
+            }
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(tiled) = &mut self.tiled {
+            ctx.request_repaint();
+
+            let (is_rendering, error) = {
+                let stat = self.stat.lock().unwrap();
+                (stat.is_rendering, stat.error.clone())
+            };
+            if let Some(error) = error {
+                log::error!("Failed to render tile {}: {error}", tiled.tile_idx);
+                self.tiled = None;
+                self.param.tile = None;
+            } else if is_rendering {
+                tiled.started = true;
+            } else if tiled.started {
+                let state = frame.wgpu_render_state().unwrap();
+                let renderer = state.renderer.read();
+                let ray_tracer: &RayTracer = renderer.callback_resources.get().unwrap();
+                let tile_image = ray_tracer.read_image(&state.device, &state.queue);
+
+                let tile = tiled.tiles[tiled.tile_idx];
+                image::imageops::replace(
+                    &mut tiled.image,
+                    &tile_image,
+                    tile.offset.x as i64,
+                    tile.offset.y as i64,
+                );
+                tiled.tile_idx += 1;
+
+                if let Some(tile) = tiled.tiles.get(tiled.tile_idx) {
+                    tiled.started = false;
+                    self.param.tile = Some(*tile);
+                } else {
+                    if let Err(err) = tiled.image.save(&self.tiled_path) {
+                        log::error!("Failed to save {}: {err}", self.tiled_path);
+                    }
+                    self.tiled = None;
+                    self.param.tile = None;
+                }
+            }
+        }
+
+        if self.animation_frame_changed {
+            let state = frame.wgpu_render_state().unwrap();
+            let mut renderer = state.renderer.write();
+            let ray_tracer: &mut RayTracer = renderer.callback_resources.get_mut().unwrap();
+
+            let time = self.animation_frame as f32;
+            if let Some(camera) = self.timeline.camera_at(time) {
+                self.param.camera = camera;
+            }
+            ray_tracer.set_object_transforms(
+                &state.device,
+                &state.queue,
+                &self.timeline.object_transforms(time),
+            );
+            self.animation_frame_changed = false;
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::Frame::canvas(ui.style()).show(ui, |ui| {
                 self.paint_canvas(ui);
@@ -291,7 +1492,7 @@ impl egui_wgpu::CallbackTrait for RayTracerCallback {
     ) -> Vec<wgpu::CommandBuffer> {
         let ray_tracer: &mut ray_tracer::RayTracer = resources.get_mut().unwrap();
 
-        ray_tracer.set_params(queue, &self.param);
+        ray_tracer.set_params(device, queue, &self.param);
         ray_tracer.update(device, queue);
 
         let mut stat = self.stat.lock().unwrap();
@@ -0,0 +1,150 @@
+use ray_tracing_wgpu::ray_tracer::{ApertureShape, CameraParam, Projection};
+
+// key of the bookmarks in the eframe storage
+const STORAGE_KEY: &str = "camera_bookmarks";
+// fallback when there is no eframe storage, which is the case natively
+#[cfg(not(target_arch = "wasm32"))]
+const FILE_PATH: &str = "camera_bookmarks.txt";
+
+#[derive(Debug, Clone)]
+pub struct Bookmark {
+    pub name: String,
+    pub camera: CameraParam,
+}
+
+pub fn load(storage: Option<&dyn eframe::Storage>) -> Vec<Bookmark> {
+    if let Some(text) = storage.and_then(|storage| storage.get_string(STORAGE_KEY)) {
+        return from_string(&text);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Ok(text) = std::fs::read_to_string(FILE_PATH) {
+        return from_string(&text);
+    }
+
+    Vec::new()
+}
+
+pub fn save(storage: Option<&mut (dyn eframe::Storage + 'static)>, bookmarks: &[Bookmark]) {
+    let text = to_string(bookmarks);
+
+    match storage {
+        Some(storage) => storage.set_string(STORAGE_KEY, text),
+        #[cfg(not(target_arch = "wasm32"))]
+        None => {
+            if let Err(err) = std::fs::write(FILE_PATH, text) {
+                log::error!("Failed to save camera bookmarks to {FILE_PATH}: {err}");
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        None => {}
+    }
+}
+
+// one bookmark per line as its name and the camera parameters as key=value
+// pairs, separated by tabs
+fn to_string(bookmarks: &[Bookmark]) -> String {
+    bookmarks
+        .iter()
+        .map(|bookmark| {
+            let c = &bookmark.camera;
+            let p = &c.physical;
+            let name = bookmark.name.replace(['\t', '\n'], " ");
+            [
+                name,
+                format!(
+                    "position={} {} {}",
+                    c.position.x, c.position.y, c.position.z
+                ),
+                format!("yaw={}", c.yaw),
+                format!("pitch={}", c.pitch),
+                format!("roll={}", c.roll),
+                format!("projection={:?}", c.projection),
+                format!("use_physical={}", c.use_physical),
+                format!("sensor_width={}", p.sensor_width),
+                format!("focal_length={}", p.focal_length),
+                format!("f_stop={}", p.f_stop),
+                format!("shutter_speed={}", p.shutter_speed),
+                format!("iso={}", p.iso),
+                format!("fov={}", c.fov),
+                format!("aperture={}", c.aperture),
+                format!("aperture_shape={:?}", c.aperture_shape),
+                format!("aperture_blades={}", c.aperture_blades),
+                format!("aperture_rotation={}", c.aperture_rotation),
+                format!("cat_eye={}", c.cat_eye),
+                format!("focus_distance={}", c.focus_distance),
+                format!("shutter_open={}", c.shutter_open),
+                format!("shutter_close={}", c.shutter_close),
+            ]
+            .join("\t")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// unknown or malformed values are left at their defaults
+fn from_string(text: &str) -> Vec<Bookmark> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split('\t');
+            let name = fields.next().unwrap_or_default().to_string();
+
+            let mut camera = CameraParam::default();
+            for (key, value) in fields.filter_map(|field| field.split_once('=')) {
+                let c = &mut camera;
+                let p = &mut c.physical;
+                match key {
+                    "position" => {
+                        let v: Vec<f32> = value.split(' ').filter_map(|v| v.parse().ok()).collect();
+                        if let [x, y, z] = v[..] {
+                            c.position = cgmath::vec3(x, y, z);
+                        }
+                    }
+                    "yaw" => parse(value, &mut c.yaw),
+                    "pitch" => parse(value, &mut c.pitch),
+                    "roll" => parse(value, &mut c.roll),
+                    "projection" => {
+                        c.projection = match value {
+                            "Orthographic" => Projection::Orthographic,
+                            "Fisheye" => Projection::Fisheye,
+                            "Equirectangular" => Projection::Equirectangular,
+                            "Cylindrical" => Projection::Cylindrical,
+                            _ => Projection::Perspective,
+                        }
+                    }
+                    "use_physical" => parse(value, &mut c.use_physical),
+                    "sensor_width" => parse(value, &mut p.sensor_width),
+                    "focal_length" => parse(value, &mut p.focal_length),
+                    "f_stop" => parse(value, &mut p.f_stop),
+                    "shutter_speed" => parse(value, &mut p.shutter_speed),
+                    "iso" => parse(value, &mut p.iso),
+                    "fov" => parse(value, &mut c.fov),
+                    "aperture" => parse(value, &mut c.aperture),
+                    "aperture_shape" => {
+                        c.aperture_shape = match value {
+                            "Polygon" => ApertureShape::Polygon,
+                            "Mask" => ApertureShape::Mask,
+                            _ => ApertureShape::Circle,
+                        }
+                    }
+                    "aperture_blades" => parse(value, &mut c.aperture_blades),
+                    "aperture_rotation" => parse(value, &mut c.aperture_rotation),
+                    "cat_eye" => parse(value, &mut c.cat_eye),
+                    "focus_distance" => parse(value, &mut c.focus_distance),
+                    "shutter_open" => parse(value, &mut c.shutter_open),
+                    "shutter_close" => parse(value, &mut c.shutter_close),
+                    _ => log::warn!("Unknown camera bookmark key {key}"),
+                }
+            }
+
+            Bookmark { name, camera }
+        })
+        .collect()
+}
+
+fn parse<T: std::str::FromStr>(value: &str, target: &mut T) {
+    if let Ok(value) = value.parse() {
+        *target = value;
+    }
+}
@@ -1,4 +1,5 @@
 mod app;
+mod bookmark;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub fn main() -> eframe::Result {
@@ -23,7 +24,13 @@ pub fn main() -> eframe::Result {
 
                     wgpu::DeviceDescriptor {
                         label: Some("egui wgpu device"),
-                        required_features: wgpu::Features::default(),
+                        // for the GPU timings of the performance panel and the
+                        // ray query hit algorithm
+                        required_features: adapter.features()
+                            & (wgpu::Features::TIMESTAMP_QUERY
+                                | wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES
+                                | wgpu::Features::EXPERIMENTAL_RAY_TRACING_ACCELERATION_STRUCTURE
+                                | wgpu::Features::EXPERIMENTAL_RAY_QUERY),
                         required_limits: wgpu::Limits {
                             max_storage_buffer_binding_size: 512_u32 << 20, // 512 MB
                             ..base_limits
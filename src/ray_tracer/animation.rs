@@ -0,0 +1,300 @@
+use super::{
+    CameraParam,
+    motion::Motion,
+    object::{Material, ObjPrim, Object},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    CatmullRom,
+}
+
+// transform of an object relative to its placement in the scene, rotating and
+// scaling about the center of its bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObjectTransform {
+    pub translation: cgmath::Vector3<f32>,
+    // euler angles in degrees, applied in x, y, z order
+    pub rotation: cgmath::Vector3<f32>,
+    pub scale: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectTrack {
+    // index of the object in the scene
+    pub object_idx: usize,
+    pub keyframes: Vec<(f32, ObjectTransform)>,
+}
+
+// keyframes of the camera and the objects, placed at frame numbers and sorted
+// by them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub num_frame: u32,
+    pub interpolation: Interpolation,
+    pub cameras: Vec<(f32, CameraParam)>,
+    pub objects: Vec<ObjectTrack>,
+}
+
+// object with its motion replaced by the animation
+pub(super) struct Animated<'a> {
+    pub object: &'a dyn Object,
+    pub motion: Option<&'a Motion>,
+}
+
+impl Timeline {
+    // None if there is no camera keyframe
+    pub fn camera_at(&self, frame: f32) -> Option<CameraParam> {
+        sample(&self.cameras, frame, self.interpolation, CameraParam::lerp)
+    }
+
+    // transforms of the animated objects at the start and the end of the
+    // frame, which span the [0, 1] time range of object motions that the
+    // shutter interval is taken within
+    pub fn object_transforms(&self, frame: f32) -> Vec<(usize, ObjectTransform, ObjectTransform)> {
+        self.objects
+            .iter()
+            .filter_map(|track| {
+                let at = |time| {
+                    sample(
+                        &track.keyframes,
+                        time,
+                        self.interpolation,
+                        ObjectTransform::lerp,
+                    )
+                };
+                Some((track.object_idx, at(frame)?, at(frame + 1.0)?))
+            })
+            .collect()
+    }
+
+    // replaces the keyframe at the same frame if there is one
+    pub fn add_camera(&mut self, frame: f32, camera: CameraParam) {
+        insert(&mut self.cameras, frame, camera);
+    }
+
+    pub fn add_object(&mut self, object_idx: usize, frame: f32, transform: ObjectTransform) {
+        let track = match self.objects.iter().position(|t| t.object_idx == object_idx) {
+            Some(i) => &mut self.objects[i],
+            None => {
+                self.objects.push(ObjectTrack {
+                    object_idx,
+                    keyframes: Vec::new(),
+                });
+                self.objects.last_mut().unwrap()
+            }
+        };
+        insert(&mut track.keyframes, frame, transform);
+    }
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            num_frame: 60,
+            interpolation: Interpolation::CatmullRom,
+            cameras: Vec::new(),
+            objects: Vec::new(),
+        }
+    }
+}
+
+impl ObjectTransform {
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation + (other.translation - self.translation) * t,
+            rotation: self.rotation + (other.rotation - self.rotation) * t,
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+
+    pub fn matrix(&self, pivot: cgmath::Vector3<f32>) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(pivot + self.translation)
+            * cgmath::Matrix4::from_angle_z(cgmath::Deg(self.rotation.z))
+            * cgmath::Matrix4::from_angle_y(cgmath::Deg(self.rotation.y))
+            * cgmath::Matrix4::from_angle_x(cgmath::Deg(self.rotation.x))
+            * cgmath::Matrix4::from_scale(self.scale)
+            * cgmath::Matrix4::from_translation(-pivot)
+    }
+}
+
+impl Default for ObjectTransform {
+    fn default() -> Self {
+        Self {
+            translation: cgmath::vec3(0.0, 0.0, 0.0),
+            rotation: cgmath::vec3(0.0, 0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+}
+
+impl Object for Animated<'_> {
+    fn as_prims(&self) -> Vec<Box<dyn ObjPrim>> {
+        self.object.as_prims()
+    }
+
+    fn material(&self) -> &Material {
+        self.object.material()
+    }
+
+    fn motion(&self) -> Option<&Motion> {
+        self.motion.or_else(|| self.object.motion())
+    }
+
+    fn backdrop(&self) -> bool {
+        self.object.backdrop()
+    }
+}
+
+// holds the first and the last keyframes beyond the ends
+fn sample<T: Clone>(
+    keyframes: &[(f32, T)],
+    time: f32,
+    interpolation: Interpolation,
+    lerp: impl Fn(&T, &T, f32) -> T,
+) -> Option<T> {
+    let (first, last) = (keyframes.first()?, keyframes.last()?);
+    if time <= first.0 {
+        return Some(first.1.clone());
+    }
+    if time >= last.0 {
+        return Some(last.1.clone());
+    }
+
+    let i = keyframes.iter().position(|(t, _)| *t > time)?;
+    let (t1, p1) = &keyframes[i - 1];
+    let (t2, p2) = &keyframes[i];
+    let s = (time - t1) / (t2 - t1);
+
+    match interpolation {
+        Interpolation::Linear => Some(lerp(p1, p2, s)),
+        Interpolation::CatmullRom => {
+            // uniform Catmull-Rom spline evaluated as the Barry-Goldman
+            // pyramid of linear interpolations, with the end keyframes
+            // repeated
+            let p0 = &keyframes[i.saturating_sub(2)].1;
+            let p3 = &keyframes[(i + 1).min(keyframes.len() - 1)].1;
+
+            let a1 = lerp(p0, p1, s + 1.0);
+            let a2 = lerp(p1, p2, s);
+            let a3 = lerp(p2, p3, s - 1.0);
+            let b1 = lerp(&a1, &a2, (s + 1.0) / 2.0);
+            let b2 = lerp(&a2, &a3, s / 2.0);
+            Some(lerp(&b1, &b2, s))
+        }
+    }
+}
+
+fn insert<T>(keyframes: &mut Vec<(f32, T)>, time: f32, value: T) {
+    match keyframes.iter().position(|(t, _)| *t >= time) {
+        Some(i) if keyframes[i].0 == time => keyframes[i].1 = value,
+        Some(i) => keyframes.insert(i, (time, value)),
+        None => keyframes.push((time, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(x: f32) -> ObjectTransform {
+        ObjectTransform {
+            translation: cgmath::vec3(x, 0.0, 0.0),
+            ..Default::default()
+        }
+    }
+
+    fn timeline(interpolation: Interpolation) -> Timeline {
+        let mut timeline = Timeline {
+            interpolation,
+            ..Default::default()
+        };
+        timeline.add_object(3, 0.0, translation(0.0));
+        timeline.add_object(3, 10.0, translation(10.0));
+        timeline.add_object(3, 20.0, translation(30.0));
+        timeline
+    }
+
+    #[test]
+    fn object_transforms_span_the_frame() {
+        let timeline = timeline(Interpolation::Linear);
+        let transforms = timeline.object_transforms(4.0);
+
+        assert_eq!(transforms, vec![(3, translation(4.0), translation(5.0))]);
+    }
+
+    #[test]
+    fn motion_at_shutter_times_follows_the_track() {
+        let timeline = timeline(Interpolation::Linear);
+        let (_, start, end) = timeline.object_transforms(4.0)[0];
+
+        // the motion is linear over the frame, so any time within the
+        // shutter lands where the track is at that time
+        for shutter_time in [0.0, 0.25, 0.5, 1.0] {
+            let moved = start.lerp(&end, shutter_time);
+            let expected = sample(
+                &timeline.objects[0].keyframes,
+                4.0 + shutter_time,
+                Interpolation::Linear,
+                ObjectTransform::lerp,
+            )
+            .unwrap();
+            assert!((moved.translation.x - expected.translation.x).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn sample_clamps_outside_the_keyframes() {
+        let keyframes = &timeline(Interpolation::Linear).objects[0].keyframes;
+        let at = |time| {
+            sample(
+                keyframes,
+                time,
+                Interpolation::Linear,
+                ObjectTransform::lerp,
+            )
+        };
+
+        assert_eq!(at(-5.0), Some(translation(0.0)));
+        assert_eq!(at(25.0), Some(translation(30.0)));
+        assert_eq!(at(15.0), Some(translation(20.0)));
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_the_keyframes() {
+        let keyframes = &timeline(Interpolation::CatmullRom).objects[0].keyframes;
+        for (time, transform) in keyframes {
+            let at = sample(
+                keyframes,
+                *time,
+                Interpolation::CatmullRom,
+                ObjectTransform::lerp,
+            );
+            assert!((at.unwrap().translation.x - transform.translation.x).abs() < 1e-4);
+        }
+
+        // and near them from either side
+        let before = sample(
+            keyframes,
+            9.999,
+            Interpolation::CatmullRom,
+            ObjectTransform::lerp,
+        );
+        assert!((before.unwrap().translation.x - 10.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn sample_without_keyframes_is_none() {
+        let keyframes: Vec<(f32, ObjectTransform)> = Vec::new();
+        assert_eq!(
+            sample(
+                &keyframes,
+                0.0,
+                Interpolation::Linear,
+                ObjectTransform::lerp
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use std::f32::consts::PI;
+
+use cgmath::InnerSpace;
+
+const MASK_SIZE: u32 = 256;
+
+// heart curve (x^2 + y^2 - 1)^3 - x^2 y^3 <= 0
+pub fn heart() -> image::GrayImage {
+    mask(|x, y| {
+        let (x, y) = (x * 1.3, y * 1.3 + 0.2);
+        (x * x + y * y - 1.0).powi(3) - x * x * y * y * y <= 0.0
+    })
+}
+
+pub fn star(points: u32) -> image::GrayImage {
+    mask(|x, y| {
+        // fold the point into the half sector between a tip (pointing up) and
+        // the notch next to it, and test against the edge in between
+        let sector = PI / points as f32;
+        let angle = ((y.atan2(x) - PI / 2.0 + sector).rem_euclid(2.0 * sector) - sector).abs();
+        let p = cgmath::vec2(x, y).magnitude() * cgmath::vec2(angle.cos(), angle.sin());
+
+        let tip = cgmath::vec2(1.0, 0.0);
+        let notch = 0.45 * cgmath::vec2(sector.cos(), sector.sin());
+        let edge = notch - tip;
+        edge.x * (p.y - tip.y) - edge.y * (p.x - tip.x) >= 0.0
+    })
+}
+
+fn mask(inside: impl Fn(f32, f32) -> bool) -> image::GrayImage {
+    image::GrayImage::from_fn(MASK_SIZE, MASK_SIZE, |i, j| {
+        // y axis points up in the aperture plane
+        let x = (i as f32 + 0.5) / MASK_SIZE as f32 * 2.0 - 1.0;
+        let y = 1.0 - (j as f32 + 0.5) / MASK_SIZE as f32 * 2.0;
+        image::Luma([if inside(x, y) { 255 } else { 0 }])
+    })
+}
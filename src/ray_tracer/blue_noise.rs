@@ -0,0 +1,128 @@
+use super::util;
+
+const SIZE: usize = 64;
+const SIGMA: f32 = 1.5;
+// the kernel is negligible beyond this distance
+const RADIUS: usize = 8;
+
+// blue noise dither texture by the void-and-cluster method [Ulichney 1993],
+// where every pixel is ranked by the order it is added to an evenly spread
+// pattern, and the ranks are quantized to the pixel values
+pub fn texture() -> image::GrayImage {
+    let mut pattern = BinaryPattern::new();
+
+    // initial pattern, made even by moving the point in the tightest cluster
+    // to the largest void until that is where it came from
+    let num_initial = SIZE * SIZE / 10;
+    while pattern.count < num_initial {
+        pattern.add(util::random_range(0..SIZE * SIZE));
+    }
+    loop {
+        let cluster = pattern.tightest_cluster();
+        pattern.remove(cluster);
+        let void = pattern.largest_void();
+        pattern.add(void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; SIZE * SIZE];
+
+    // rank the initial points by removing them from the tightest cluster
+    let mut removing = pattern.clone();
+    while removing.count > 0 {
+        let cluster = removing.tightest_cluster();
+        removing.remove(cluster);
+        rank[cluster] = removing.count;
+    }
+
+    // rank the rest by filling the largest void, which is also the tightest
+    // cluster of the pixels left empty
+    while pattern.count < SIZE * SIZE {
+        let void = pattern.largest_void();
+        rank[void] = pattern.count;
+        pattern.add(void);
+    }
+
+    image::GrayImage::from_fn(SIZE as u32, SIZE as u32, |x, y| {
+        let rank = rank[x as usize + y as usize * SIZE];
+        image::Luma([(rank * 256 / (SIZE * SIZE)) as u8])
+    })
+}
+
+#[derive(Clone)]
+struct BinaryPattern {
+    set: Vec<bool>,
+    count: usize,
+    // sum of the gaussian kernel of the set pixels at each pixel
+    energy: Vec<f32>,
+    // gaussian kernel of the toroidal offsets
+    kernel: Vec<f32>,
+}
+
+impl BinaryPattern {
+    fn new() -> Self {
+        let kernel = (0..SIZE * SIZE)
+            .map(|i| {
+                let wrap = |d: usize| d.min(SIZE - d) as f32;
+                let (dx, dy) = (wrap(i % SIZE), wrap(i / SIZE));
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            })
+            .collect();
+
+        Self {
+            set: vec![false; SIZE * SIZE],
+            count: 0,
+            energy: vec![0.0; SIZE * SIZE],
+            kernel,
+        }
+    }
+
+    fn add(&mut self, idx: usize) {
+        if !self.set[idx] {
+            self.set[idx] = true;
+            self.count += 1;
+            self.splat(idx, 1.0);
+        }
+    }
+
+    fn remove(&mut self, idx: usize) {
+        if self.set[idx] {
+            self.set[idx] = false;
+            self.count -= 1;
+            self.splat(idx, -1.0);
+        }
+    }
+
+    fn splat(&mut self, idx: usize, sign: f32) {
+        let (x, y) = (idx % SIZE, idx / SIZE);
+        for dy in SIZE - RADIUS..=SIZE + RADIUS {
+            for dx in SIZE - RADIUS..=SIZE + RADIUS {
+                let i = (x + dx) % SIZE + (y + dy) % SIZE * SIZE;
+                self.energy[i] += sign * self.kernel[dx % SIZE + dy % SIZE * SIZE];
+            }
+        }
+    }
+
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme(&self, set: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        (0..SIZE * SIZE)
+            .filter(|&i| self.set[i] == set)
+            .reduce(|best, i| {
+                if better(self.energy[i], self.energy[best]) {
+                    i
+                } else {
+                    best
+                }
+            })
+            .unwrap()
+    }
+}
@@ -1,4 +1,7 @@
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
 
 use image::GenericImageView;
 use wgpu::util::DeviceExt;
@@ -81,15 +84,44 @@ impl<const RO: bool> StorageBuffer<RO> {
         Self::new_with_slice(device, writer.as_ref(), label)
     }
 
+    // zero initialized by wgpu, without staging the zeros from the CPU
     pub fn new_with_size(device: &wgpu::Device, size: usize, label: Option<&str>) -> Self {
-        Self::new_with_slice(device, &vec![0u8; size], label)
+        let label = label.map(|l| format!("{l} Storage Buffer"));
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: label.as_deref(),
+            size: size as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self { buffer }
+    }
+
+    // zero initialized like new_with_size, and also usable as the arguments of
+    // indirect dispatches
+    pub fn new_indirect(device: &wgpu::Device, size: usize, label: Option<&str>) -> Self {
+        let label = label.map(|l| format!("{l} Storage Buffer"));
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: label.as_deref(),
+            size: size as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self { buffer }
     }
 
     pub fn new_with_slice(device: &wgpu::Device, data: &[u8], label: Option<&str>) -> Self {
         let label = label.map(|l| format!("{l} Storage Buffer"));
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             contents: data,
-            usage: wgpu::BufferUsages::STORAGE,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
             label: label.as_deref(),
         });
 
@@ -97,6 +129,130 @@ impl<const RO: bool> StorageBuffer<RO> {
             buffer,
         }
     }
+
+    // writes the data in place if it fits the buffer exactly, or else
+    // reallocates the buffer, in which case true is returned for the bind
+    // groups to be recreated
+    pub fn set_data<T: encase::ShaderType + encase::internal::WriteInto>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &T,
+        label: Option<&str>,
+    ) -> bool {
+        let mut writer = encase::StorageBuffer::new(Vec::<u8>::new());
+
+        writer.write(data).unwrap();
+
+        if writer.as_ref().len() as u64 == self.buffer.size() {
+            queue.write_buffer(&self.buffer, 0, writer.as_ref());
+            return false;
+        }
+
+        *self = Self::new_with_slice(device, writer.as_ref(), label);
+        true
+    }
+
+    // blocks until the first size bytes of the buffer are read back, which is
+    // only possible natively
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue, size: u64) -> Vec<u8> {
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Read Back Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &staging, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+
+        let data = slice.get_mapped_range().to_vec();
+        staging.unmap();
+        data
+    }
+}
+
+// staging buffer for reading back a buffer without blocking, the copy
+// is encoded first, mapped once submitted and taken after the device is polled
+pub struct ReadBuffer {
+    buffer: wgpu::Buffer,
+    state: Arc<Mutex<ReadState>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReadState {
+    Idle,
+    Copied,
+    Mapping,
+    Ready,
+}
+
+impl ReadBuffer {
+    pub fn new(device: &wgpu::Device, size: u64, label: Option<&str>) -> Self {
+        let label = label.map(|l| format!("{l} Read Buffer"));
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: label.as_deref(),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            state: Arc::new(Mutex::new(ReadState::Idle)),
+        }
+    }
+
+    // returns false if the previous read is not taken yet
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::Buffer) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if *state != ReadState::Idle {
+            return false;
+        }
+
+        encoder.copy_buffer_to_buffer(source, 0, &self.buffer, 0, self.buffer.size());
+        *state = ReadState::Copied;
+        true
+    }
+
+    // to be called after the copy is submitted
+    pub fn map(&self) {
+        let mut state = self.state.lock().unwrap();
+        if *state != ReadState::Copied {
+            return;
+        }
+
+        let callback_state = self.state.clone();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                *callback_state.lock().unwrap() = match result {
+                    Ok(()) => ReadState::Ready,
+                    Err(_) => ReadState::Idle,
+                };
+            });
+        *state = ReadState::Mapping;
+    }
+
+    pub fn take(&self) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        if *state != ReadState::Ready {
+            return None;
+        }
+
+        let data = self.buffer.slice(..).get_mapped_range().to_vec();
+        self.buffer.unmap();
+        *state = ReadState::Idle;
+        Some(data)
+    }
 }
 
 impl<const RO: bool> Layout for StorageBuffer<RO> {
@@ -207,6 +363,40 @@ impl Texture {
 
         texture
     }
+
+    pub fn from_luma(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::GrayImage,
+        label: Option<&str>,
+    ) -> Self {
+        let dimensions = img.dimensions();
+
+        let texture = Self::new(
+            device,
+            wgpu::TextureFormat::R8Unorm,
+            (dimensions.0, dimensions.1),
+            label,
+        );
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            img,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(dimensions.0),
+                rows_per_image: Some(dimensions.1),
+            },
+            texture.size,
+        );
+
+        texture
+    }
 }
 
 impl Layout for Texture {
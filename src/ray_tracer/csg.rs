@@ -0,0 +1,176 @@
+use super::{
+    object::{AABB, Material, ObjPrim, Object, prims_bbox},
+    shader_type,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+// constructive solid geometry of two closed objects, the materials of the
+// operands are ignored in favor of the material of the CSG node, operands
+// that are CSG nodes themselves are rejected as the shader cannot nest them
+pub struct Csg {
+    op: CsgOp,
+    a: Box<dyn Object>,
+    b: Box<dyn Object>,
+    material: Material,
+}
+
+pub struct CsgPrim {
+    pub op: CsgOp,
+    pub a: Vec<Box<dyn ObjPrim>>,
+    pub b: Vec<Box<dyn ObjPrim>>,
+    pub bbox: AABB,
+}
+
+#[allow(unused)]
+impl Csg {
+    pub fn new(
+        op: CsgOp,
+        a: Box<dyn Object>,
+        b: Box<dyn Object>,
+        material: Material,
+    ) -> Result<Self, String> {
+        let nested = |object: &dyn Object| object.as_prims().iter().any(|p| p.csg().is_some());
+        if nested(a.as_ref()) || nested(b.as_ref()) {
+            return Err("Nested CSG is not supported".to_string());
+        }
+
+        Ok(Self { op, a, b, material })
+    }
+
+    pub fn union(
+        a: Box<dyn Object>,
+        b: Box<dyn Object>,
+        material: Material,
+    ) -> Result<Self, String> {
+        Self::new(CsgOp::Union, a, b, material)
+    }
+
+    pub fn intersection(
+        a: Box<dyn Object>,
+        b: Box<dyn Object>,
+        material: Material,
+    ) -> Result<Self, String> {
+        Self::new(CsgOp::Intersection, a, b, material)
+    }
+
+    pub fn difference(
+        a: Box<dyn Object>,
+        b: Box<dyn Object>,
+        material: Material,
+    ) -> Result<Self, String> {
+        Self::new(CsgOp::Difference, a, b, material)
+    }
+}
+
+impl Object for Csg {
+    fn as_prims(&self) -> Vec<Box<dyn ObjPrim>> {
+        let a = self.a.as_prims();
+        let b = self.b.as_prims();
+
+        let bbox_a = prims_bbox(&a);
+        let bbox_b = prims_bbox(&b);
+        let bbox = match self.op {
+            CsgOp::Union => bbox_a.union(&bbox_b),
+            CsgOp::Intersection => bbox_a.intersection(&bbox_b),
+            CsgOp::Difference => bbox_a,
+        };
+
+        vec![Box::new(CsgPrim {
+            op: self.op,
+            a,
+            b,
+            bbox,
+        })]
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+impl ObjPrim for CsgPrim {
+    // the operand ranges are filled in once the operands are placed in the
+    // object list
+    fn as_shader_type(&self, mat_idx: u32) -> shader_type::Object {
+        shader_type::Object {
+            obj_type: shader_type::OBJ_CSG,
+            mat_idx,
+            data_idx: match self.op {
+                CsgOp::Union => shader_type::CSG_UNION,
+                CsgOp::Intersection => shader_type::CSG_INTERSECTION,
+                CsgOp::Difference => shader_type::CSG_DIFFERENCE,
+            },
+            motion_idx: -1,
+            v: [
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+            ],
+            n: [
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+            ],
+        }
+    }
+
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn csg(&self) -> Option<&CsgPrim> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray_tracer::object::Sphere;
+
+    fn sphere(x: f32) -> Box<dyn Object> {
+        let albedo = cgmath::vec3(0.5, 0.5, 0.5);
+        Box::new(Sphere::new(
+            cgmath::vec3(x, 0.0, 0.0),
+            1.0,
+            Material::Diffuse { albedo },
+        ))
+    }
+
+    #[test]
+    fn nested_csg_is_rejected() {
+        let material = Material::Diffuse {
+            albedo: cgmath::vec3(0.5, 0.5, 0.5),
+        };
+        let inner = Csg::union(sphere(0.0), sphere(1.0), material.clone()).unwrap();
+
+        assert!(Csg::difference(Box::new(inner), sphere(2.0), material).is_err());
+    }
+
+    #[test]
+    fn union_bounds_both_operands() {
+        let material = Material::Diffuse {
+            albedo: cgmath::vec3(0.5, 0.5, 0.5),
+        };
+        let csg = Csg::union(sphere(0.0), sphere(3.0), material).unwrap();
+        let bbox = csg.as_prims()[0].bbox().clone();
+
+        assert_eq!(bbox.min.x, -1.0);
+        assert_eq!(bbox.max.x, 4.0);
+    }
+
+    #[test]
+    fn no_prims_have_empty_bounds() {
+        let bbox = prims_bbox(&[]);
+        let other = AABB::new(cgmath::vec3(1.0, 2.0, 3.0), cgmath::vec3(4.0, 5.0, 6.0));
+
+        assert_eq!(bbox.union(&other).min, other.min);
+        assert_eq!(bbox.union(&other).max, other.max);
+    }
+}
@@ -0,0 +1,629 @@
+use super::{
+    buffer, create_bind_group, create_bind_group_with_layout, object::AABB, refit, shader_type,
+};
+use crate::wgpu;
+
+// mirrors of the constants in lbvh.wgsl
+const WORKGROUP_SIZE: u32 = 256;
+const BLOCK_SIZE: u32 = WORKGROUP_SIZE * 16;
+const RADIX: usize = 16;
+// bits of the Morton codes, sorted 4 bits per pass, with an even number of
+// passes so that the sorted items end up where they started
+const MORTON_BITS: u32 = 30;
+const NUM_PASS: usize = 8;
+
+// size of BVHNode, and of the header of BVH before the nodes
+const NODE_SIZE: usize = 80;
+const BVH_HEADER_SIZE: usize = 16;
+const COST_SCALE: f64 = 65536.0;
+
+// linear BVH builder on the GPU [Karras 2012], which sorts the objects by the
+// Morton codes of their centroids, emits the radix tree over the codes and
+// fits the bounds from the leaves up, into the same layout as the CPU builder
+// with the internal nodes first and the leaves after them
+pub struct Lbvh {
+    bounds_pipeline: wgpu::ComputePipeline,
+    morton_pipeline: wgpu::ComputePipeline,
+    histogram_pipeline: wgpu::ComputePipeline,
+    scan_pipeline: wgpu::ComputePipeline,
+    scatter_pipeline: wgpu::ComputePipeline,
+    hierarchy_pipeline: wgpu::ComputePipeline,
+    fit_pipeline: wgpu::ComputePipeline,
+    leaves_pipeline: wgpu::ComputePipeline,
+    cost_pipeline: wgpu::ComputePipeline,
+
+    bind_group_layout: wgpu::BindGroupLayout,
+
+    // the last tree built, kept on the GPU to be refitted
+    tree: Option<Tree>,
+}
+
+// a tree with the buffers it was built in, and its SAH cost as built and as
+// last refitted, which are read back without blocking and so lag behind
+struct Tree {
+    buffers: Buffers,
+    bind_group: wgpu::BindGroup,
+    cost_read: buffer::ReadBuffer,
+    // whether the cost being read back is of the tree as built
+    reading_built: bool,
+    built_cost: Option<f32>,
+    cost: Option<f32>,
+}
+
+// working buffers of a build, sized to the objects
+struct Buffers {
+    // one per pass, for the digit it sorts
+    params: Vec<buffer::UniformBuffer<shader_type::LbvhParam>>,
+    bboxes: buffer::StorageBuffer<true>,
+    bvh: buffer::StorageBuffer<false>,
+    items: [buffer::StorageBuffer<false>; 2],
+    histogram: buffer::StorageBuffer<false>,
+    centroid_bounds: buffer::StorageBuffer<false>,
+    cost: buffer::StorageBuffer<false>,
+    num_object: u32,
+}
+
+impl Lbvh {
+    pub fn new(device: &wgpu::Device) -> Self {
+        // the layout is taken from placeholder buffers, as the buffers of each
+        // build are sized to its objects
+        let placeholder = Buffers::new(device, &[(AABB::default(), AABB::default())]);
+        let (bind_group_layout, _) = create_bind_group(
+            device,
+            &placeholder.bindings(0),
+            wgpu::ShaderStages::COMPUTE,
+            Some("Ray Tracer LBVH"),
+        );
+
+        let shader_source = [
+            include_str!("shader/type.wgsl"),
+            include_str!("shader/lbvh.wgsl"),
+        ]
+        .join("\n");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Ray Tracer LBVH Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Ray Tracer LBVH Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(&format!("Ray Tracer LBVH {entry_point} Pipeline")),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            bounds_pipeline: pipeline("lbvh_bounds"),
+            morton_pipeline: pipeline("lbvh_morton"),
+            histogram_pipeline: pipeline("lbvh_histogram"),
+            scan_pipeline: pipeline("lbvh_scan"),
+            scatter_pipeline: pipeline("lbvh_scatter"),
+            hierarchy_pipeline: pipeline("lbvh_hierarchy"),
+            fit_pipeline: pipeline("lbvh_fit"),
+            leaves_pipeline: pipeline("lbvh_leaves"),
+            cost_pipeline: pipeline("lbvh_cost"),
+
+            bind_group_layout,
+
+            tree: None,
+        }
+    }
+
+    // builds the BVH over the bounding boxes of the objects at the start and
+    // the end of the shutter interval, which fails if it exceeds what the
+    // device can bind or dispatch
+    pub fn build(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bboxes: &[(AABB, AABB)],
+    ) -> Result<buffer::StorageBuffer<true>, String> {
+        self.tree = None;
+        if bboxes.is_empty() {
+            return Err("LBVH needs at least one object".to_string());
+        }
+
+        let num_object = bboxes.len() as u32;
+        let num_workgroup = num_object.div_ceil(WORKGROUP_SIZE);
+        let num_block = num_object.div_ceil(BLOCK_SIZE);
+
+        let limits = device.limits();
+        let max_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        let size = bvh_size(bboxes.len()) as u64;
+        if size > max_size {
+            return Err(format!(
+                "LBVH needs {} MB for the nodes, beyond the {} MB the device can bind",
+                size >> 20,
+                max_size >> 20,
+            ));
+        }
+        if num_workgroup > limits.max_compute_workgroups_per_dimension {
+            return Err(format!(
+                "LBVH of {num_object} objects needs more workgroups than the device can dispatch"
+            ));
+        }
+
+        let buffers = Buffers::new(device, bboxes);
+        let bind_groups: Vec<wgpu::BindGroup> = (0..NUM_PASS)
+            .map(|pass| {
+                create_bind_group_with_layout(
+                    device,
+                    &self.bind_group_layout,
+                    &buffers.bindings(pass),
+                    Some("Ray Tracer LBVH"),
+                )
+            })
+            .collect();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Ray Tracer LBVH Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Ray Tracer LBVH Pass"),
+                timestamp_writes: None,
+            });
+
+            compute_pass.set_bind_group(0, &bind_groups[0], &[]);
+            compute_pass.set_pipeline(&self.bounds_pipeline);
+            compute_pass.dispatch_workgroups(num_workgroup, 1, 1);
+            compute_pass.set_pipeline(&self.morton_pipeline);
+            compute_pass.dispatch_workgroups(num_workgroup, 1, 1);
+
+            for bind_group in &bind_groups {
+                compute_pass.set_bind_group(0, bind_group, &[]);
+                compute_pass.set_pipeline(&self.histogram_pipeline);
+                compute_pass.dispatch_workgroups(num_block, 1, 1);
+                compute_pass.set_pipeline(&self.scan_pipeline);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+                compute_pass.set_pipeline(&self.scatter_pipeline);
+                compute_pass.dispatch_workgroups(num_block, 1, 1);
+            }
+
+            compute_pass.set_bind_group(0, &bind_groups[0], &[]);
+            compute_pass.set_pipeline(&self.hierarchy_pipeline);
+            compute_pass.dispatch_workgroups(num_workgroup, 1, 1);
+
+            self.fit(&mut compute_pass, num_object);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        let mut tree = Tree {
+            cost_read: buffer::ReadBuffer::new(
+                device,
+                size_of::<u64>() as u64,
+                Some("Ray Tracer LBVH Cost"),
+            ),
+            bind_group: bind_groups.into_iter().next().unwrap(),
+            buffers,
+            reading_built: true,
+            built_cost: None,
+            cost: None,
+        };
+        self.measure(device, queue, &mut tree, num_object);
+
+        let bvh = buffer::StorageBuffer::<true> {
+            buffer: tree.buffers.bvh.buffer.clone(),
+        };
+        self.tree = Some(tree);
+        Ok(bvh)
+    }
+
+    // refits the last tree built in place to the moved bounding boxes of the
+    // same objects, false if there is no such tree
+    pub fn refit(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bboxes: &[(AABB, AABB)],
+    ) -> bool {
+        let Some(mut tree) = self.tree.take() else {
+            return false;
+        };
+        let num_object = bboxes.len() as u32;
+        if tree.buffers.num_object != num_object {
+            return false;
+        }
+
+        // of the same size, so written in place under the same bind group
+        tree.buffers.bboxes.set_data(
+            device,
+            queue,
+            &shader_bboxes(bboxes),
+            Some("Ray Tracer LBVH Bounding Boxes"),
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Ray Tracer LBVH Refit Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Ray Tracer LBVH Refit Pass"),
+                timestamp_writes: None,
+            });
+
+            compute_pass.set_bind_group(0, &tree.bind_group, &[]);
+            compute_pass.set_pipeline(&self.leaves_pipeline);
+            compute_pass.dispatch_workgroups(num_object.div_ceil(WORKGROUP_SIZE), 1, 1);
+
+            self.fit(&mut compute_pass, num_object);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        tree.take_cost();
+        self.measure(device, queue, &mut tree, num_object);
+        self.tree = Some(tree);
+        true
+    }
+
+    // whether the SAH cost of the refitted tree, as far as it is read back,
+    // has grown beyond the ratio to that of the tree as built
+    pub fn degraded(&mut self, ratio: f32) -> bool {
+        let Some(tree) = self.tree.as_mut() else {
+            return false;
+        };
+
+        tree.take_cost();
+        match (tree.built_cost, tree.cost) {
+            (Some(built_cost), Some(cost)) => refit::degraded(built_cost, cost, ratio),
+            _ => false,
+        }
+    }
+
+    // the prefixes of the nodes lengthen down the tree, to at most the bits of
+    // the codes and then of the indices of equal codes
+    fn fit(&self, compute_pass: &mut wgpu::ComputePass, num_object: u32) {
+        compute_pass.set_pipeline(&self.fit_pipeline);
+        for _ in 0..max_depth(num_object) {
+            compute_pass.dispatch_workgroups(num_object.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+
+    // sums the SAH cost of the tree into its read buffer, unless the last one
+    // is still being read back
+    fn measure(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tree: &mut Tree,
+        num_object: u32,
+    ) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Ray Tracer LBVH Cost Encoder"),
+        });
+        encoder.clear_buffer(&tree.buffers.cost.buffer, 0, None);
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Ray Tracer LBVH Cost Pass"),
+                timestamp_writes: None,
+            });
+
+            compute_pass.set_bind_group(0, &tree.bind_group, &[]);
+            compute_pass.set_pipeline(&self.cost_pipeline);
+            compute_pass.dispatch_workgroups(num_object.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+
+        if tree.cost_read.copy(&mut encoder, &tree.buffers.cost.buffer) {
+            queue.submit(Some(encoder.finish()));
+            tree.cost_read.map();
+        }
+    }
+}
+
+impl Tree {
+    // the cost read back first is of the tree as built, and the later ones of
+    // the tree as refitted
+    fn take_cost(&mut self) {
+        let Some(data) = self.cost_read.take() else {
+            return;
+        };
+
+        let word = |i: usize| u32::from_ne_bytes(data[4 * i..4 * i + 4].try_into().unwrap());
+        let cost = ((word(0) as u64 | (word(1) as u64) << 32) as f64 / COST_SCALE) as f32;
+        if self.reading_built {
+            self.built_cost = Some(cost);
+            self.reading_built = false;
+        } else {
+            self.cost = Some(cost);
+        }
+    }
+}
+
+impl Buffers {
+    fn new(device: &wgpu::Device, bboxes: &[(AABB, AABB)]) -> Self {
+        let num_object = bboxes.len() as u32;
+        let num_block = num_object.div_ceil(BLOCK_SIZE);
+
+        let params = (0..NUM_PASS)
+            .map(|pass| {
+                buffer::UniformBuffer::new(
+                    device,
+                    &shader_type::LbvhParam {
+                        num_object,
+                        num_block,
+                        shift: 4 * pass as u32,
+                    },
+                    Some("Ray Tracer LBVH Parameter"),
+                )
+            })
+            .collect();
+
+        let items = || {
+            buffer::StorageBuffer::<false>::new_with_size(
+                device,
+                num_object as usize * 2 * size_of::<u32>(),
+                Some("Ray Tracer LBVH Items"),
+            )
+        };
+
+        // the bounds start empty for the atomics, as ordered bits of the
+        // largest and the smallest floats
+        let empty_bounds: Vec<u8> = [u32::MAX; 3]
+            .into_iter()
+            .chain([0; 3])
+            .flat_map(u32::to_ne_bytes)
+            .collect();
+
+        Self {
+            params,
+            bboxes: buffer::StorageBuffer::<true>::new(
+                device,
+                &shader_bboxes(bboxes),
+                Some("Ray Tracer LBVH Bounding Boxes"),
+            ),
+            bvh: buffer::StorageBuffer::<false>::new_with_size(
+                device,
+                bvh_size(num_object as usize),
+                Some("Ray Tracer BVH"),
+            ),
+            items: [items(), items()],
+            histogram: buffer::StorageBuffer::<false>::new_with_size(
+                device,
+                RADIX * num_block as usize * size_of::<u32>(),
+                Some("Ray Tracer LBVH Histogram"),
+            ),
+            centroid_bounds: buffer::StorageBuffer::<false>::new_with_slice(
+                device,
+                &empty_bounds,
+                Some("Ray Tracer LBVH Centroid Bounds"),
+            ),
+            cost: buffer::StorageBuffer::<false>::new_with_size(
+                device,
+                size_of::<u64>(),
+                Some("Ray Tracer LBVH Cost"),
+            ),
+            num_object,
+        }
+    }
+
+    // the items are sorted back and forth between the two buffers
+    fn bindings(&self, pass: usize) -> [&dyn buffer::Layout; 8] {
+        [
+            &self.params[pass],
+            &self.bboxes,
+            &self.bvh,
+            &self.items[pass % 2],
+            &self.items[(pass + 1) % 2],
+            &self.histogram,
+            &self.centroid_bounds,
+            &self.cost,
+        ]
+    }
+}
+
+// boxes of each object at the start and the end of the shutter interval
+fn shader_bboxes(bboxes: &[(AABB, AABB)]) -> Vec<shader_type::AABB> {
+    bboxes
+        .iter()
+        .flat_map(|(bbox, bbox_end)| [bbox.as_shader_type(), bbox_end.as_shader_type()])
+        .collect()
+}
+
+fn bvh_size(num_object: usize) -> usize {
+    BVH_HEADER_SIZE + (2 * num_object.max(1) - 1) * NODE_SIZE
+}
+
+// levels of internal nodes the tree can have
+fn max_depth(num_object: u32) -> u32 {
+    MORTON_BITS + num_object.next_power_of_two().trailing_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // traversal stack of compute.wgsl
+    const BVH_MAX_STACK: usize = 64;
+
+    // nodes of a BVH built on the GPU over the objects, blocks until they are
+    // read back
+    fn read(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bvh: &buffer::StorageBuffer<true>,
+        num_object: usize,
+    ) -> shader_type::BVH {
+        let data = bvh.read(device, queue, bvh_size(num_object) as u64);
+        encase::StorageBuffer::new(data).create().unwrap()
+    }
+
+    // the tests that need it are ignored, to be run with --ignored where
+    // there is an adapter
+    fn device() -> (wgpu::Device, wgpu::Queue) {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+                .expect("no GPU adapter");
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+            .unwrap()
+    }
+
+    // boxes scattered over a few units, some of them moving, with repeated
+    // centroids so that equal Morton codes are split by their indices
+    fn bboxes(num_object: usize) -> Vec<(AABB, AABB)> {
+        (0..num_object)
+            .map(|i| {
+                let t = (i % (num_object / 2).max(1)) as f32;
+                let center = cgmath::vec3((t * 1.3).sin(), (t * 2.9).cos(), (t * 0.7).sin()) * 4.0;
+                let bbox = AABB::new(center, center + cgmath::vec3(0.1, 0.2, 0.3));
+                let offset = cgmath::vec3(0.0, (i % 3) as f32 * 0.5, 0.0);
+                let bbox_end = AABB::new(bbox.min + offset, bbox.max + offset);
+                (bbox, bbox_end)
+            })
+            .collect()
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn lbvh_matches_cpu_builder() {
+        let (device, queue) = device();
+        let mut lbvh = Lbvh::new(&device);
+
+        // over one and several rounds and blocks of the sort
+        for num_object in [1, 2, 3, 100, 300, BLOCK_SIZE + 7, 3 * BLOCK_SIZE] {
+            let num_object = num_object as usize;
+            let bboxes = bboxes(num_object);
+            let bvh = lbvh.build(&device, &queue, &bboxes).unwrap();
+            let bvh = read(&device, &queue, &bvh, num_object);
+            if let Err(error) = check(&bvh, &bboxes) {
+                panic!("{num_object} objects: {error}");
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn refit_fits_moved_objects() {
+        let (device, queue) = device();
+        let mut lbvh = Lbvh::new(&device);
+
+        let num_object = 300;
+        let bvh = lbvh.build(&device, &queue, &bboxes(num_object)).unwrap();
+
+        // the objects swap places, so that the tree is much looser refitted
+        let moved: Vec<(AABB, AABB)> = bboxes(num_object).into_iter().rev().collect();
+        assert!(lbvh.refit(&device, &queue, &moved));
+        let refitted = read(&device, &queue, &bvh, num_object);
+        check(&refitted, &moved).unwrap();
+
+        device.poll(wgpu::Maintain::Wait);
+        let tree = lbvh.tree.as_mut().unwrap();
+        tree.take_cost();
+        let (built_cost, cost) = (tree.built_cost.unwrap(), tree.cost.unwrap());
+
+        // up to the float sums in different orders
+        let expected = refit::sah_cost(&refitted);
+        assert!((cost - expected).abs() < 1e-4 * expected);
+        assert!(cost > built_cost);
+        assert!(lbvh.degraded(1.0));
+        assert!(!lbvh.degraded(0.0));
+
+        // a tree of other objects cannot be refitted
+        assert!(!lbvh.refit(&device, &queue, &bboxes(num_object + 1)));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn lbvh_of_no_objects_fails() {
+        let (device, queue) = device();
+        assert!(Lbvh::new(&device).build(&device, &queue, &[]).is_err());
+    }
+
+    // compares the BVH built on the GPU with the CPU builder, which splits the
+    // objects differently but has to bound the same objects by the same root
+    fn check(bvh: &shader_type::BVH, bboxes: &[(AABB, AABB)]) -> Result<(), String> {
+        let nodes = &bvh.nodes;
+        let expected = crate::ray_tracer::object::build_bvh(bboxes);
+
+        if nodes.len() != 2 * bboxes.len().max(1) - 1 {
+            return Err(format!(
+                "{} nodes for {} objects",
+                nodes.len(),
+                bboxes.len()
+            ));
+        }
+        if !same_bounds(
+            &nodes[0],
+            &expected.nodes[0].bbox,
+            &expected.nodes[0].bbox_end,
+        ) {
+            return Err("the root bounds differ from the CPU builder".to_string());
+        }
+
+        let mut seen = vec![false; bboxes.len()];
+        let mut stack = vec![(0, 1)];
+        while let Some((idx, depth)) = stack.pop() {
+            let node = nodes
+                .get(idx as usize)
+                .ok_or(format!("node {idx} is out of range"))?;
+            if depth >= BVH_MAX_STACK {
+                return Err(format!("node {idx} is too deep to traverse"));
+            }
+
+            if node.object_idx >= 0 {
+                let object_idx = node.object_idx as usize;
+                if seen.get(object_idx) != Some(&false) {
+                    return Err(format!("object {object_idx} is out of range or repeated"));
+                }
+                seen[object_idx] = true;
+
+                let (bbox, bbox_end) = &bboxes[object_idx];
+                if !same_bounds(node, &bbox.as_shader_type(), &bbox_end.as_shader_type()) {
+                    return Err(format!("leaf of object {object_idx} has other bounds"));
+                }
+                continue;
+            }
+
+            let (left, right) = (node.left_idx, node.right_idx);
+            let (Some(left_node), Some(right_node)) =
+                (nodes.get(left as usize), nodes.get(right as usize))
+            else {
+                return Err(format!("children of node {idx} are out of range"));
+            };
+            let union = |a: &shader_type::AABB, b: &shader_type::AABB| shader_type::AABB {
+                min: cgmath::vec3(
+                    a.min.x.min(b.min.x),
+                    a.min.y.min(b.min.y),
+                    a.min.z.min(b.min.z),
+                ),
+                max: cgmath::vec3(
+                    a.max.x.max(b.max.x),
+                    a.max.y.max(b.max.y),
+                    a.max.z.max(b.max.z),
+                ),
+            };
+            let bbox = union(&left_node.bbox, &right_node.bbox);
+            let bbox_end = union(&left_node.bbox_end, &right_node.bbox_end);
+            if !same_bounds(node, &bbox, &bbox_end) {
+                return Err(format!("node {idx} does not fit its children"));
+            }
+            stack.push((left, depth + 1));
+            stack.push((right, depth + 1));
+        }
+
+        match seen.iter().position(|seen| !seen) {
+            Some(object_idx) => Err(format!("object {object_idx} is not in the tree")),
+            None => Ok(()),
+        }
+    }
+
+    fn same_bounds(
+        node: &shader_type::BVHNode,
+        bbox: &shader_type::AABB,
+        bbox_end: &shader_type::AABB,
+    ) -> bool {
+        node.bbox.min == bbox.min
+            && node.bbox.max == bbox.max
+            && node.bbox_end.min == bbox_end.min
+            && node.bbox_end.max == bbox_end.max
+    }
+}
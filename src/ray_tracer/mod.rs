@@ -1,11 +1,26 @@
+mod animation;
+mod aperture;
+mod blue_noise;
 mod buffer;
+mod csg;
+mod lbvh;
+mod motion;
 mod object;
+mod ray_query;
+mod refit;
 mod scene;
+mod sdf;
 mod shader_type;
+mod timer;
 mod util;
+mod wavefront;
 
+use cgmath::{ElementWise, InnerSpace};
 use std::{collections::HashMap, mem::size_of};
 
+pub use animation::{Interpolation, ObjectTrack, ObjectTransform, Timeline};
+pub use timer::GpuTime;
+
 use crate::{
     time::{Duration, Instant},
     wgpu,
@@ -14,23 +29,77 @@ use crate::{
 const WORKGROUP_SIZE_X: u32 = 16;
 const WORKGROUP_SIZE_Y: u32 = 16;
 
-const MAX_WINDOW_SIZE_X: u32 = 1920;
-const MAX_WINDOW_SIZE_Y: u32 = 1080;
+// size of Pixel in the frame buffer, which is padded to 16 bytes alignment
+const PIXEL_SIZE: usize = 8 * size_of::<f32>();
+// size of Convergence
+const CONVERGENCE_SIZE: usize = 5 * size_of::<u32>();
+
+// bounds of the samples per dispatch, the upper one keeps a dispatch short
+// enough not to stall the display
+const MAX_SAMPLES_PER_DISPATCH: u32 = 64;
 
 pub struct RayTracer {
     stat: Stat,
     param: Param,
+    scene: Vec<Box<dyn object::Object>>,
+    // bounds of the bulk of the scene, for navigation
+    scene_bbox: (cgmath::Vector3<f32>, cgmath::Vector3<f32>),
+    // of the top level objects at the start and the end of the shutter
+    // interval, which the BVH is built over
+    bboxes: Vec<(object::AABB, object::AABB)>,
+    // None where the BVH built on the GPU is not read back
+    refit: Option<refit::Refit>,
 
     stat_uniform: buffer::UniformBuffer<shader_type::Stat>,
     param_uniform: buffer::UniformBuffer<shader_type::Param>,
 
     render_pipeline: wgpu::RenderPipeline,
     render_uniform_bind_group: wgpu::BindGroup,
+    render_storage_bind_group_layout: wgpu::BindGroupLayout,
     render_storage_bind_group: wgpu::BindGroup,
 
     compute_pipeline: wgpu::ComputePipeline,
     compute_uniform_bind_group: wgpu::BindGroup,
+    compute_storage_bind_group_layout: wgpu::BindGroupLayout,
     compute_storage_bind_group: wgpu::BindGroup,
+    compute_texture_bind_group_layout: wgpu::BindGroupLayout,
+    compute_texture_bind_group: wgpu::BindGroup,
+    compute_reproject_pipeline: wgpu::ComputePipeline,
+    compute_reproject_bind_group_layout: wgpu::BindGroupLayout,
+    compute_reproject_bind_group: wgpu::BindGroup,
+    wavefront: wavefront::Wavefront,
+    lbvh: lbvh::Lbvh,
+
+    // sized to the display, reallocated with the bind groups as it changes
+    frame_buffer_storage: buffer::StorageBuffer<false>,
+    bvh_storage: buffer::StorageBuffer<true>,
+    objects_storage: buffer::StorageBuffer<true>,
+    material_storage: buffer::StorageBuffer<true>,
+    motion_storage: buffer::StorageBuffer<true>,
+    convergence_storage: buffer::StorageBuffer<false>,
+    convergence_read: buffer::ReadBuffer,
+    // copy of the frame buffer warped to the new view when the camera moves
+    history_storage: buffer::StorageBuffer<true>,
+    previous_camera_uniform: buffer::UniformBuffer<shader_type::Camera>,
+    // camera the accumulation was rendered from, when it is to be reprojected
+    // before the next dispatch
+    reproject_from: Option<shader_type::Camera>,
+    // renders counted by resets, to drop read backs of previous renders
+    generation: u32,
+    // render and samples per pixel of the dispatch being read back
+    convergence_generation: u32,
+    convergence_num_sample: u32,
+    // samples per dispatch tuned to the target frame time, kept across
+    // renders
+    auto_samples: u32,
+    last_dispatch: Option<Instant>,
+    // rays traced per sample of every pixel, from the last read back
+    rays_per_sample: f32,
+    timer: Option<timer::GpuTimer>,
+    aperture_mask: buffer::Texture,
+    blue_noise: buffer::Texture,
+    // None without ray query support
+    acceleration_structure: Option<ray_query::AccelerationStructure>,
 }
 
 #[derive(Debug, Clone)]
@@ -38,23 +107,98 @@ pub struct Stat {
     pub is_rendering: bool,
     pub frame_counter: u32,
     pub time_spent: Duration,
+    // mean relative error of the pixels and the number of pixels still being
+    // sampled, once read back from the GPU
+    pub noise: Option<f32>,
+    pub active_pixels: Option<u32>,
+    pub samples_per_dispatch: u32,
+    // pixel samples and rays traced per second, by the GPU time of the
+    // dispatches if it is known
+    pub samples_per_second: f32,
+    pub rays_per_second: f32,
+    // in milliseconds, the GPU times if timestamp queries are supported, or
+    // else the time between dispatches
+    pub gpu_time: Option<GpuTime>,
+    pub frame_time: f32,
+    // why rendering is not possible, if it is not
+    pub error: Option<String>,
     time_start: Instant,
+    // index of the next sample in the random sequences, which carries on
+    // across reprojections so that the new samples do not repeat the history
+    sample_index: u32,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Param {
     pub camera: CameraParam,
     pub display_size: cgmath::Vector2<u32>,
+    // rendered pixels per displayed pixel along each axis, and the filter
+    // resampling the render to the display
+    pub render_scale: f32,
+    pub display_filter: DisplayFilter,
+    // part of a larger image to render instead of the display, at its own size
+    pub tile: Option<Tile>,
+    // offset and size in rendered pixels of the region to render, leaving the
+    // rest of the image as it was
+    pub region: Option<(cgmath::Vector2<u32>, cgmath::Vector2<u32>)>,
+    pub kernel: Kernel,
     pub hit_algorithm: HitAlgorithm,
+    pub bvh_builder: BvhBuilder,
+    // whether the BVH is refitted to moved objects instead of rebuilt, and
+    // the growth of its SAH cost over the cost as built beyond which it is
+    // rebuilt anyway, at least 1, or 0 to never rebuild it
+    pub bvh_refit: bool,
+    pub bvh_rebuild_ratio: f32,
     pub shading_algorithm: ShadingAlgorithm,
+    pub sampler: Sampler,
+    pub filter: Filter,
+    // in pixels
+    pub filter_radius: f32,
     pub max_sample: u32,
     pub max_bounce: u32,
+    // relative error at which pixels stop being sampled, 0 to disable
+    pub noise_threshold: f32,
+    // in seconds, 0 for no limit
+    pub time_budget: f32,
+    pub samples_per_dispatch: u32,
+    // in milliseconds, tunes the samples per dispatch to it unless 0
+    pub target_frame_time: f32,
+    // carries the accumulation over camera moves by reprojecting it, instead
+    // of starting over
+    pub reproject: bool,
+}
+
+// region of an image in pixels, with the y axis pointing down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+    pub image_size: cgmath::Vector2<u32>,
+    pub offset: cgmath::Vector2<u32>,
+    pub size: cgmath::Vector2<u32>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum HitAlgorithm {
     Brute,
     BVH,
+    // hardware traversal of the triangles through ray queries, falling back
+    // to the BVH on devices without them
+    RayQuery,
+}
+
+// how the BVH is built, on the CPU by recursive splits or on the GPU as a
+// linear BVH over the Morton codes of the objects
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BvhBuilder {
+    Cpu,
+    Lbvh,
+}
+
+// how the paths are traced, the megakernel traces a whole path in each
+// invocation while the wavefront splits it into a kernel per stage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kernel {
+    Megakernel,
+    Wavefront,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -63,31 +207,112 @@ pub enum ShadingAlgorithm {
     Smooth,
 }
 
+// source of the random numbers of the samples, the low-discrepancy samplers
+// converge faster while PCG is plain Monte Carlo
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampler {
+    Pcg,
+    Sobol,
+    BlueNoise,
+}
+
+// pixel reconstruction filter, the box filter averages the samples within the
+// pixel while the others also weight in samples of the neighboring pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Box,
+    Gaussian,
+    Mitchell,
+    BlackmanHarris,
+    Lanczos,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayFilter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Fisheye,
+    Equirectangular,
+    Cylindrical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApertureShape {
+    Circle,
+    Polygon,
+    Mask,
+}
+
+#[derive(Debug, Clone)]
+pub enum ApertureMask {
+    Heart,
+    Star,
+    Image(image::GrayImage),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct CameraParam {
     pub position: cgmath::Vector3<f32>,
     pub yaw: f32,
     pub pitch: f32,
+    pub roll: f32,
+    pub projection: Projection,
+    // derive the field of view, aperture and exposure from the physical
+    // camera instead of using fov and aperture directly
+    pub use_physical: bool,
+    pub physical: PhysicalCamera,
+    // vertical field of view in degrees, the image height of fisheye and
+    // cylindrical projections, unused by the equirectangular projection
     pub fov: f32,
     pub aperture: f32,
+    pub aperture_shape: ApertureShape,
+    // number of blades and rotation in degrees of polygonal apertures
+    pub aperture_blades: u32,
+    pub aperture_rotation: f32,
+    // strength of the cat-eye vignetting, which clips the aperture towards the
+    // edges of the image
+    pub cat_eye: f32,
     pub focus_distance: f32,
+    // shutter interval within the [0, 1] time range of object motions
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+}
+
+// camera body and lens settings, with scene units taken as meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalCamera {
+    // in millimeters
+    pub sensor_width: f32,
+    pub focal_length: f32,
+    pub f_stop: f32,
+    // in seconds
+    pub shutter_speed: f32,
+    pub iso: f32,
 }
 
 impl RayTracer {
-    pub fn new(
-        device: &wgpu::Device,
-        _queue: &wgpu::Queue,
-        target: wgpu::ColorTargetState,
-    ) -> Self {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, target: wgpu::ColorTargetState) -> Self {
         let stat = Stat::default();
         let param = Param::default();
 
-        let objects = scene::random_spheres();
-        let (bvh, objects, materials) = object::as_shader_types(&objects);
+        let scene = scene::random_spheres();
+        let scene_bbox = object::scene_bbox(&scene);
+        let scene_bbox = (scene_bbox.min, scene_bbox.max);
+        let (bboxes, objects, materials, motions, sdf_source) = object::as_shader_types(&scene);
 
         /* resource-----------------------------------------------------------*/
-        let stat_uniform =
-            buffer::UniformBuffer::new(device, &stat.as_shader_type(), Some("Ray Tracer State"));
+        let stat_uniform = buffer::UniformBuffer::new(
+            device,
+            &stat.as_shader_type(true),
+            Some("Ray Tracer State"),
+        );
         let param_uniform = buffer::UniformBuffer::new(
             device,
             &param.clone().as_shader_type(),
@@ -95,14 +320,39 @@ impl RayTracer {
         );
         let frame_buffer_storage = buffer::StorageBuffer::<false>::new_with_size(
             device,
-            MAX_WINDOW_SIZE_X as usize * MAX_WINDOW_SIZE_Y as usize * 4 * size_of::<f32>(),
+            frame_buffer_size(param.render_size()) as usize,
             Some("Ray Tracer Frame Buffer"),
         );
-        let bvh_storage = buffer::StorageBuffer::<true>::new(device, &bvh, Some("Ray Tracer BVH"));
+        let convergence_storage = buffer::StorageBuffer::<false>::new_with_size(
+            device,
+            CONVERGENCE_SIZE,
+            Some("Ray Tracer Convergence"),
+        );
+        let convergence_read = buffer::ReadBuffer::new(
+            device,
+            CONVERGENCE_SIZE as u64,
+            Some("Ray Tracer Convergence"),
+        );
+        let refit = refit::Refit::new(object::build_bvh(&bboxes));
+        let bvh_storage =
+            buffer::StorageBuffer::<true>::new(device, refit.bvh(), Some("Ray Tracer BVH"));
         let objects_storage =
             buffer::StorageBuffer::<true>::new(device, &objects, Some("Ray Tracer Objects"));
         let material_storage =
             buffer::StorageBuffer::<true>::new(device, &materials, Some("Ray Tracer Materials"));
+        let motion_storage =
+            buffer::StorageBuffer::<true>::new(device, &motions, Some("Ray Tracer Motions"));
+        let acceleration_structure = ray_query::AccelerationStructure::new(device, queue, &objects);
+        let history_storage = buffer::StorageBuffer::<true>::new_with_size(
+            device,
+            history_size(&param) as usize,
+            Some("Ray Tracer History"),
+        );
+        let previous_camera_uniform = buffer::UniformBuffer::new(
+            device,
+            &param.as_shader_type().camera,
+            Some("Ray Tracer Previous Camera"),
+        );
 
         /* render shader------------------------------------------------------*/
         let render_shader_source = [
@@ -166,7 +416,15 @@ impl RayTracer {
             include_str!("shader/type.wgsl"),
             include_str!("shader/util.wgsl"),
             include_str!("shader/graphics.wgsl"),
+            include_str!("shader/sdf.wgsl"),
+            &sdf_source,
             include_str!("shader/compute.wgsl"),
+            include_str!("shader/wavefront.wgsl"),
+            if acceleration_structure.is_some() {
+                include_str!("shader/ray_query.wgsl")
+            } else {
+                include_str!("shader/ray_query_fallback.wgsl")
+            },
         ]
         .join("\n");
         let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -188,17 +446,61 @@ impl RayTracer {
                 &bvh_storage,
                 &objects_storage,
                 &material_storage,
+                &motion_storage,
+                &convergence_storage,
             ],
             wgpu::ShaderStages::COMPUTE,
             Some("Ray Tracer Compute Storage"),
         );
 
+        let aperture_mask = buffer::Texture::from_luma(
+            device,
+            queue,
+            &image::GrayImage::from_pixel(1, 1, image::Luma([255])),
+            Some("Ray Tracer Aperture Mask"),
+        );
+        let blue_noise = buffer::Texture::from_luma(
+            device,
+            queue,
+            &blue_noise::texture(),
+            Some("Ray Tracer Blue Noise"),
+        );
+        let mut textures: Vec<&dyn buffer::Layout> = vec![&aperture_mask, &blue_noise];
+        if let Some(acceleration_structure) = &acceleration_structure {
+            textures.push(acceleration_structure);
+        }
+        let (compute_texture_bind_group_layout, compute_texture_bind_group) = create_bind_group(
+            device,
+            &textures,
+            wgpu::ShaderStages::COMPUTE,
+            Some("Ray Tracer Compute Texture"),
+        );
+
         let compute_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Ray Tracer Compute Pipeline Layout"),
                 bind_group_layouts: &[
                     &compute_uniform_bind_group_layout,
                     &compute_storage_bind_group_layout,
+                    &compute_texture_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let (compute_reproject_bind_group_layout, compute_reproject_bind_group) = create_bind_group(
+            device,
+            &[&previous_camera_uniform, &history_storage],
+            wgpu::ShaderStages::COMPUTE,
+            Some("Ray Tracer Compute Reproject"),
+        );
+        let compute_reproject_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Ray Tracer Compute Reproject Pipeline Layout"),
+                bind_group_layouts: &[
+                    &compute_uniform_bind_group_layout,
+                    &compute_storage_bind_group_layout,
+                    &compute_texture_bind_group_layout,
+                    &compute_reproject_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             });
@@ -219,21 +521,78 @@ impl RayTracer {
             },
             cache: None,
         });
+        let compute_reproject_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Ray Tracer Compute Reproject Pipeline"),
+                layout: Some(&compute_reproject_pipeline_layout),
+                module: &compute_shader,
+                entry_point: Some("cs_reproject"),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &compute_shader_constants,
+                    ..Default::default()
+                },
+                cache: None,
+            });
+
+        let wavefront = wavefront::Wavefront::new(
+            device,
+            &compute_shader,
+            [
+                &compute_uniform_bind_group_layout,
+                &compute_storage_bind_group_layout,
+                &compute_texture_bind_group_layout,
+            ],
+            &compute_shader_constants,
+        );
 
         Self {
             stat,
             param,
+            scene,
+            scene_bbox,
+            bboxes,
+            refit: Some(refit),
 
             stat_uniform,
             param_uniform,
 
             render_pipeline,
             render_uniform_bind_group,
+            render_storage_bind_group_layout,
             render_storage_bind_group,
 
             compute_pipeline,
             compute_uniform_bind_group,
+            compute_storage_bind_group_layout,
             compute_storage_bind_group,
+            compute_texture_bind_group_layout,
+            compute_texture_bind_group,
+            compute_reproject_pipeline,
+            compute_reproject_bind_group_layout,
+            compute_reproject_bind_group,
+            wavefront,
+            lbvh: lbvh::Lbvh::new(device),
+
+            frame_buffer_storage,
+            bvh_storage,
+            objects_storage,
+            material_storage,
+            motion_storage,
+            convergence_storage,
+            convergence_read,
+            history_storage,
+            previous_camera_uniform,
+            reproject_from: None,
+            generation: 0,
+            convergence_generation: 0,
+            convergence_num_sample: 0,
+            auto_samples: 1,
+            last_dispatch: None,
+            rays_per_sample: 0.0,
+            timer: timer::GpuTimer::new(device, queue),
+            aperture_mask,
+            blue_noise,
+            acceleration_structure,
         }
     }
 
@@ -241,17 +600,345 @@ impl RayTracer {
         &self.param
     }
 
-    pub fn set_params(&mut self, queue: &wgpu::Queue, param: &Param) {
+    pub fn set_params(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, param: &Param) {
         if self.param == *param {
             return;
         }
 
+        // the display filter only changes how the accumulation is shown
+        let display_only = *param
+            == Param {
+                display_filter: param.display_filter,
+                ..self.param.clone()
+            };
+
+        // only the camera moved since samples were taken, which the
+        // accumulation can be reprojected across, while the lens, exposure
+        // and shutter change the samples themselves
+        let camera = &param.camera;
+        let camera_only = param.reproject
+            && self.stat.frame_counter > 0
+            && *param
+                == Param {
+                    camera: CameraParam {
+                        position: camera.position,
+                        yaw: camera.yaw,
+                        pitch: camera.pitch,
+                        roll: camera.roll,
+                        projection: camera.projection,
+                        fov: camera.fov,
+                        ..self.param.camera.clone()
+                    },
+                    ..self.param.clone()
+                };
+        let previous_camera = self.param.as_shader_type().camera;
+        let rebuild_bvh = param.bvh_builder != self.param.bvh_builder;
+
         self.param_uniform.set_data(queue, &param.as_shader_type());
         self.param = param.clone();
 
+        if camera_only {
+            let sample_index = self.stat.sample_index;
+            self.reset();
+            self.stat.sample_index = sample_index;
+            self.reproject_from = Some(previous_camera);
+        } else if !display_only {
+            self.reset();
+            self.resize_frame_buffer(device);
+        }
+
+        if rebuild_bvh {
+            self.build_bvh(device, queue);
+        }
+    }
+
+    pub fn scene_bbox(&self) -> (cgmath::Vector3<f32>, cgmath::Vector3<f32>) {
+        self.scene_bbox
+    }
+
+    pub fn num_objects(&self) -> usize {
+        self.scene.len()
+    }
+
+    // whether HitAlgorithm::RayQuery runs on the hardware
+    pub fn supports_ray_query(&self) -> bool {
+        self.acceleration_structure.is_some()
+    }
+
+    // moves the objects by the transforms at the start and the end of the
+    // frame, replacing their own motions, other objects are left in place
+    pub fn set_object_transforms(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        transforms: &[(usize, ObjectTransform, ObjectTransform)],
+    ) {
+        let mut motions: Vec<Option<motion::Motion>> = self.scene.iter().map(|_| None).collect();
+        for (idx, start, end) in transforms {
+            let Some(object) = self.scene.get(*idx) else {
+                log::warn!("Animated object {idx} is not in the scene");
+                continue;
+            };
+            let bbox = object::prims_bbox(&object.as_prims());
+            let pivot = (bbox.min + bbox.max) / 2.0;
+            motions[*idx] = Some(motion::Motion::linear(
+                start.matrix(pivot),
+                end.matrix(pivot),
+            ));
+        }
+
+        let (bboxes, objects, materials, motions, _) = {
+            let scene: Vec<Box<dyn object::Object + '_>> = self
+                .scene
+                .iter()
+                .zip(&motions)
+                .map(|(object, motion)| {
+                    Box::new(animation::Animated {
+                        object: object.as_ref(),
+                        motion: motion.as_ref(),
+                    }) as Box<dyn object::Object>
+                })
+                .collect();
+            object::as_shader_types(&scene)
+        };
+
+        // updated in place unless the motions are added or removed
+        self.bboxes = bboxes;
+        let mut rebind =
+            self.objects_storage
+                .set_data(device, queue, &objects, Some("Ray Tracer Objects"));
+        rebind |=
+            self.material_storage
+                .set_data(device, queue, &materials, Some("Ray Tracer Materials"));
+        rebind |= self
+            .motion_storage
+            .set_data(device, queue, &motions, Some("Ray Tracer Motions"));
+        if self.acceleration_structure.is_some() {
+            self.acceleration_structure =
+                ray_query::AccelerationStructure::new(device, queue, &objects);
+            self.create_texture_bind_group(device);
+        }
+
+        self.reset();
+        self.update_bvh(device, queue, rebind);
+    }
+
+    // refits the BVH to the current bounding boxes in place if its topology
+    // is kept, and rebuilds it otherwise or once refitting has degraded it
+    fn update_bvh(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, rebind: bool) {
+        if !self.param.bvh_refit {
+            self.build_bvh(device, queue);
+            return;
+        }
+
+        let reallocated = match self.param.bvh_builder {
+            BvhBuilder::Cpu => {
+                let Some(refit) = self.refit.as_mut() else {
+                    self.build_bvh(device, queue);
+                    return;
+                };
+
+                refit.refit(&self.bboxes);
+                if refit.degraded(self.param.bvh_rebuild_ratio) {
+                    self.build_bvh(device, queue);
+                    return;
+                }
+
+                self.bvh_storage
+                    .set_data(device, queue, refit.bvh(), Some("Ray Tracer BVH"))
+            }
+            // refitted on the GPU, by the cost of the refits read back so far
+            BvhBuilder::Lbvh => {
+                if self.lbvh.degraded(self.param.bvh_rebuild_ratio)
+                    || !self.lbvh.refit(device, queue, &self.bboxes)
+                {
+                    self.build_bvh(device, queue);
+                    return;
+                }
+
+                false
+            }
+        };
+        if rebind || reallocated {
+            self.create_storage_bind_groups(device);
+        }
+    }
+
+    // builds the BVH over the current bounding boxes by the selected builder,
+    // rendering is stopped with an error if the GPU builder cannot build it
+    fn build_bvh(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.refit = None;
+        match self.param.bvh_builder {
+            BvhBuilder::Cpu => {
+                let bvh = object::build_bvh(&self.bboxes);
+                self.bvh_storage =
+                    buffer::StorageBuffer::<true>::new(device, &bvh, Some("Ray Tracer BVH"));
+                self.refit = Some(refit::Refit::new(bvh));
+            }
+            BvhBuilder::Lbvh => match self.lbvh.build(device, queue, &self.bboxes) {
+                Ok(bvh_storage) => self.bvh_storage = bvh_storage,
+                Err(error) => {
+                    self.stat.is_rendering = false;
+                    self.stat.error = Some(error);
+                }
+            },
+        }
+
+        self.create_storage_bind_groups(device);
+    }
+
+    // reallocates the frame buffer to the display size, rendering is stopped
+    // with an error if it exceeds what the device can bind
+    fn resize_frame_buffer(&mut self, device: &wgpu::Device) {
+        let render_size = self.param.render_size();
+        let size = frame_buffer_size(render_size);
+        let limits = device.limits();
+        let max_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        if size > max_size {
+            self.stat.is_rendering = false;
+            self.stat.error = Some(format!(
+                "Render size {}x{} needs a frame buffer of {} MB, beyond the {} MB the device can bind",
+                render_size.x,
+                render_size.y,
+                size >> 20,
+                max_size >> 20,
+            ));
+            return;
+        }
+
+        // the paths are only allocated while the wavefront is in use
+        let num_path = match self.param.kernel {
+            Kernel::Megakernel => 0,
+            Kernel::Wavefront => render_size.x as u64 * render_size.y as u64,
+        };
+        if let Err(error) = self.wavefront.resize(device, num_path) {
+            self.stat.is_rendering = false;
+            self.stat.error = Some(error);
+            return;
+        }
+
+        let history_size = history_size(&self.param);
+        if size == self.frame_buffer_storage.buffer.size()
+            && history_size == self.history_storage.buffer.size()
+        {
+            return;
+        }
+
+        if size != self.frame_buffer_storage.buffer.size() {
+            self.frame_buffer_storage = buffer::StorageBuffer::<false>::new_with_size(
+                device,
+                size as usize,
+                Some("Ray Tracer Frame Buffer"),
+            );
+        }
+        if history_size != self.history_storage.buffer.size() {
+            self.history_storage = buffer::StorageBuffer::<true>::new_with_size(
+                device,
+                history_size as usize,
+                Some("Ray Tracer History"),
+            );
+        }
+        self.create_storage_bind_groups(device);
+    }
+
+    fn create_storage_bind_groups(&mut self, device: &wgpu::Device) {
+        self.compute_storage_bind_group = create_bind_group_with_layout(
+            device,
+            &self.compute_storage_bind_group_layout,
+            &[
+                &self.frame_buffer_storage,
+                &self.bvh_storage,
+                &self.objects_storage,
+                &self.material_storage,
+                &self.motion_storage,
+                &self.convergence_storage,
+            ],
+            Some("Ray Tracer Compute Storage"),
+        );
+        self.render_storage_bind_group = create_bind_group_with_layout(
+            device,
+            &self.render_storage_bind_group_layout,
+            &[&self.frame_buffer_storage],
+            Some("Ray Tracer Render Storage"),
+        );
+        self.compute_reproject_bind_group = create_bind_group_with_layout(
+            device,
+            &self.compute_reproject_bind_group_layout,
+            &[&self.previous_camera_uniform, &self.history_storage],
+            Some("Ray Tracer Compute Reproject"),
+        );
+    }
+
+    // current accumulation as displayed, blocks until the frame buffer is
+    // read back
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_image(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> image::RgbImage {
+        let image = self.read_image_hdr(device, queue);
+        image::RgbImage::from_fn(image.width(), image.height(), |x, y| {
+            image::Rgb(
+                image
+                    .get_pixel(x, y)
+                    .0
+                    .map(|value| (value.max(0.0).sqrt().min(1.0) * 255.0).round() as u8),
+            )
+        })
+    }
+
+    // exposed but linear and unclamped, for formats that keep the dynamic range
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_image_hdr(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> image::Rgb32FImage {
+        let render_size = self.param.render_size();
+        let (width, height) = (render_size.x, render_size.y);
+        let data = self.frame_buffer_storage.read(
+            device,
+            queue,
+            width as u64 * height as u64 * PIXEL_SIZE as u64,
+        );
+
+        let exposure = self.param.camera.exposure();
+        let float =
+            |offset: usize| f32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+        image::Rgb32FImage::from_fn(width, height, |x, y| {
+            let i = (x + y * width) as usize * PIXEL_SIZE;
+            let weight = float(i + 3 * size_of::<f32>());
+            let scale = if weight > 0.0 { exposure / weight } else { 0.0 };
+            image::Rgb(std::array::from_fn(|c| {
+                scale * float(i + c * size_of::<f32>())
+            }))
+        })
+    }
+
+    pub fn set_aperture_mask(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mask: &ApertureMask,
+    ) {
+        let mask = match mask {
+            ApertureMask::Heart => &aperture::heart(),
+            ApertureMask::Star => &aperture::star(5),
+            ApertureMask::Image(image) => image,
+        };
+        self.aperture_mask =
+            buffer::Texture::from_luma(device, queue, mask, Some("Ray Tracer Aperture Mask"));
+        self.create_texture_bind_group(device);
+
         self.reset();
     }
 
+    fn create_texture_bind_group(&mut self, device: &wgpu::Device) {
+        let mut textures: Vec<&dyn buffer::Layout> = vec![&self.aperture_mask, &self.blue_noise];
+        if let Some(acceleration_structure) = &self.acceleration_structure {
+            textures.push(acceleration_structure);
+        }
+        self.compute_texture_bind_group = create_bind_group_with_layout(
+            device,
+            &self.compute_texture_bind_group_layout,
+            &textures,
+            Some("Ray Tracer Compute Texture"),
+        );
+    }
+
     pub fn get_stat(&self) -> &Stat {
         &self.stat
     }
@@ -261,57 +948,198 @@ impl RayTracer {
             return;
         }
 
-        if self.stat.frame_counter >= self.param.max_sample {
+        device.poll(wgpu::Maintain::Poll);
+        if let Some(data) = self.convergence_read.take()
+            && self.convergence_generation == self.generation
+        {
+            let word = |i: usize| u32::from_ne_bytes(data[4 * i..4 * i + 4].try_into().unwrap());
+            let (_, dispatch_size) = self.param.dispatch_region();
+            let num_pixel = dispatch_size.x * dispatch_size.y;
+            let num_active = word(0);
+            let error_sum = (word(2) as u64) << 32 | word(1) as u64;
+            let num_ray = (word(4) as u64) << 32 | word(3) as u64;
+            self.stat.active_pixels = Some(num_active);
+            self.stat.noise = Some(error_sum as f32 / 256.0 / num_pixel.max(1) as f32);
+
+            self.rays_per_sample = num_ray as f32 / self.convergence_num_sample.max(1) as f32;
+
+            // the average time of a dispatch, unless the GPU time is known
+            let num_sample = num_active as u64 * self.convergence_num_sample as u64;
+            let dispatch_time = self.stat.time_spent.as_secs_f32() / self.stat.frame_counter as f32
+                * self.convergence_num_sample as f32;
+            if self.timer.is_none() && num_sample > 0 && dispatch_time > 0.0 {
+                self.stat.samples_per_second = num_sample as f32 / dispatch_time;
+                self.stat.rays_per_second = num_ray as f32 / dispatch_time;
+            }
+        }
+        if let Some(gpu_time) = self.timer.as_mut().and_then(|timer| timer.take()) {
+            let dispatch_time = gpu_time.compute / 1000.0;
+            let num_sample = gpu_time.num_sample as f32;
+            if let Some(num_active) = self.stat.active_pixels {
+                self.stat.samples_per_second = num_active as f32 * num_sample / dispatch_time;
+            }
+            self.stat.rays_per_second = self.rays_per_sample * num_sample / dispatch_time;
+            self.stat.gpu_time = Some(gpu_time);
+        }
+
+        let converged = self.param.noise_threshold > 0.0 && self.stat.active_pixels == Some(0);
+        let out_of_time = self.param.time_budget > 0.0
+            && self.stat.time_start.elapsed().as_secs_f32() >= self.param.time_budget;
+        if self.stat.frame_counter >= self.param.max_sample || converged || out_of_time {
             self.stat.is_rendering = false;
             self.stat.time_spent = self.stat.time_start.elapsed();
+            self.last_dispatch = None;
             return;
         }
 
+        // the time between dispatches is bound by the GPU once it exceeds the
+        // display refresh, so the samples are scaled by how far it is off the
+        // target, damped against the latency of the measurement
+        if let Some(last_dispatch) = self.last_dispatch {
+            self.stat.frame_time = last_dispatch.elapsed().as_secs_f32() * 1000.0;
+        }
+        if self.param.target_frame_time > 0.0 {
+            if self.last_dispatch.is_some() {
+                let scale = (self.param.target_frame_time / self.stat.frame_time).clamp(0.8, 1.25);
+                self.auto_samples = ((self.auto_samples as f32 * scale).round() as u32)
+                    .clamp(1, MAX_SAMPLES_PER_DISPATCH);
+            }
+            self.stat.samples_per_dispatch = self.auto_samples;
+        } else {
+            self.stat.samples_per_dispatch = self.param.samples_per_dispatch;
+        }
+        self.stat.samples_per_dispatch = self
+            .stat
+            .samples_per_dispatch
+            .min(self.param.max_sample - self.stat.frame_counter);
+        self.last_dispatch = Some(Instant::now());
+
+        let reproject_from = self.reproject_from.take();
+        let clear = self.stat.frame_counter == 0 && reproject_from.is_none();
         self.stat_uniform
-            .set_data(queue, &self.stat.as_shader_type());
+            .set_data(queue, &self.stat.as_shader_type(clear));
+        queue.write_buffer(&self.convergence_storage.buffer, 0, &[0; CONVERGENCE_SIZE]);
+        if self.param.kernel == Kernel::Wavefront {
+            self.wavefront.clear(queue);
+        }
 
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Ray Tracer Compute Encoder"),
         });
+        let timing = self
+            .timer
+            .as_mut()
+            .is_some_and(|timer| timer.resolve(&mut encoder));
 
-        {
+        // the accumulation is copied aside and warped back into the frame
+        // buffer, before the new view is sampled on top of it
+        if let Some(previous_camera) = reproject_from {
+            self.previous_camera_uniform
+                .set_data(queue, &previous_camera);
+            encoder.copy_buffer_to_buffer(
+                &self.frame_buffer_storage.buffer,
+                0,
+                &self.history_storage.buffer,
+                0,
+                self.frame_buffer_storage.buffer.size(),
+            );
+
+            let render_size = self.param.render_size();
             let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Ray Tracer Compute Pass"),
+                label: Some("Ray Tracer Reproject Pass"),
                 timestamp_writes: None,
             });
-
-            compute_pass.set_pipeline(&self.compute_pipeline);
+            compute_pass.set_pipeline(&self.compute_reproject_pipeline);
             compute_pass.set_bind_group(0, &self.compute_uniform_bind_group, &[]);
             compute_pass.set_bind_group(1, &self.compute_storage_bind_group, &[]);
+            compute_pass.set_bind_group(2, &self.compute_texture_bind_group, &[]);
+            compute_pass.set_bind_group(3, &self.compute_reproject_bind_group, &[]);
             compute_pass.dispatch_workgroups(
-                self.param.display_size.x / WORKGROUP_SIZE_X + 1,
-                self.param.display_size.y / WORKGROUP_SIZE_Y + 1,
+                render_size.x.div_ceil(WORKGROUP_SIZE_X),
+                render_size.y.div_ceil(WORKGROUP_SIZE_Y),
                 1,
             );
         }
 
+        {
+            let (_, dispatch_size) = self.param.dispatch_region();
+            let num_sample = self.stat.samples_per_dispatch;
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Ray Tracer Compute Pass"),
+                timestamp_writes: self
+                    .timer
+                    .as_mut()
+                    .map(|timer| timer.compute_pass_writes(num_sample)),
+            });
+
+            compute_pass.set_bind_group(0, &self.compute_uniform_bind_group, &[]);
+            compute_pass.set_bind_group(1, &self.compute_storage_bind_group, &[]);
+            compute_pass.set_bind_group(2, &self.compute_texture_bind_group, &[]);
+            match self.param.kernel {
+                Kernel::Megakernel => {
+                    compute_pass.set_pipeline(&self.compute_pipeline);
+                    compute_pass.dispatch_workgroups(
+                        dispatch_size.x.div_ceil(WORKGROUP_SIZE_X),
+                        dispatch_size.y.div_ceil(WORKGROUP_SIZE_Y),
+                        1,
+                    );
+                }
+                Kernel::Wavefront => self.wavefront.dispatch(
+                    &mut compute_pass,
+                    dispatch_size,
+                    num_sample,
+                    self.param.max_bounce,
+                ),
+            }
+        }
+
+        let reading = self
+            .convergence_read
+            .copy(&mut encoder, &self.convergence_storage.buffer);
         queue.submit(Some(encoder.finish()));
+        if timing {
+            self.timer.as_ref().unwrap().map();
+        }
+        if reading {
+            self.convergence_read.map();
+            self.convergence_generation = self.generation;
+            self.convergence_num_sample = self.stat.samples_per_dispatch;
+        }
 
-        self.stat.frame_counter += 1;
+        self.stat.frame_counter += self.stat.samples_per_dispatch;
+        self.stat.sample_index += self.stat.samples_per_dispatch;
         self.stat.time_spent = self.stat.time_start.elapsed();
     }
 
     pub fn render(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        if let Some(timer) = &self.timer {
+            timer.write_render_begin(render_pass);
+        }
+
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.render_uniform_bind_group, &[]);
         render_pass.set_bind_group(1, &self.render_storage_bind_group, &[]);
         render_pass.draw(0..3, 0..1);
+
+        if let Some(timer) = &self.timer {
+            timer.write_render_end(render_pass);
+        }
     }
 
     fn reset(&mut self) {
         self.stat = Stat::default();
+        self.generation = self.generation.wrapping_add(1);
+        self.last_dispatch = None;
+        self.reproject_from = None;
     }
 }
 
 impl Stat {
-    fn as_shader_type(&self) -> shader_type::Stat {
+    fn as_shader_type(&self, clear: bool) -> shader_type::Stat {
         shader_type::Stat {
-            frame_counter: self.frame_counter,
+            sample_index: self.sample_index,
+            num_sample: self.samples_per_dispatch,
+            clear: clear as u32,
         }
     }
 }
@@ -322,27 +1150,95 @@ impl Default for Stat {
             is_rendering: true,
             frame_counter: 0,
             time_spent: Duration::ZERO,
+            noise: None,
+            active_pixels: None,
+            samples_per_dispatch: 1,
+            samples_per_second: 0.0,
+            rays_per_second: 0.0,
+            gpu_time: None,
+            frame_time: 0.0,
+            error: None,
             time_start: Instant::now(),
+            sample_index: 0,
         }
     }
 }
 
 impl Param {
+    // size of the frame buffer
+    pub fn render_size(&self) -> cgmath::Vector2<u32> {
+        match self.tile {
+            Some(tile) => tile.size,
+            None => self
+                .display_size
+                .map(|size| ((size as f32 * self.render_scale).round() as u32).max(1)),
+        }
+    }
+
+    // offset and size of the pixels dispatched, the region clamped to the
+    // render size, not applied to tiles
+    pub fn dispatch_region(&self) -> (cgmath::Vector2<u32>, cgmath::Vector2<u32>) {
+        let render_size = self.render_size();
+        match self.region {
+            Some((offset, size)) if self.tile.is_none() => {
+                let offset = offset.zip(render_size, |o, r| o.min(r - 1));
+                let size = size.zip(render_size - offset, |s, r| s.clamp(1, r));
+                (offset, size)
+            }
+            _ => (cgmath::vec2(0, 0), render_size),
+        }
+    }
+
     pub fn as_shader_type(&self) -> shader_type::Param {
+        let render_size = self.render_size();
+        let (dispatch_offset, dispatch_size) = self.dispatch_region();
+        let tile = self.tile.unwrap_or(Tile {
+            image_size: render_size,
+            offset: cgmath::vec2(0, 0),
+            size: render_size,
+        });
+        let image_size = tile.image_size.map(|x| x as f32);
+        let texture = |v: cgmath::Vector2<u32>| v.map(|x| x as f32).div_element_wise(image_size);
+        let window = (texture(tile.offset), texture(tile.size));
+        let aspect_ratio = image_size.x / image_size.y;
+
         shader_type::Param {
-            camera: self
-                .camera
-                .as_shader_type(self.display_size.x as f32 / self.display_size.y as f32),
-            display_size: self.display_size,
+            camera: self.camera.as_shader_type(aspect_ratio, window),
+            display_size: render_size,
             hit_algorithm: match self.hit_algorithm {
                 HitAlgorithm::Brute => shader_type::HIT_BRUTE,
                 HitAlgorithm::BVH => shader_type::HIT_BVH,
+                HitAlgorithm::RayQuery => shader_type::HIT_RAY_QUERY,
             },
             shading_algorithm: match self.shading_algorithm {
                 ShadingAlgorithm::Flat => shader_type::SHADE_FLAT,
                 ShadingAlgorithm::Smooth => shader_type::SHADE_SMOOTH,
             },
             max_bounce: self.max_bounce,
+            sampler_type: match self.sampler {
+                Sampler::Pcg => shader_type::SAMPLER_PCG,
+                Sampler::Sobol => shader_type::SAMPLER_SOBOL,
+                Sampler::BlueNoise => shader_type::SAMPLER_BLUE_NOISE,
+            },
+            noise_threshold: self.noise_threshold,
+            filter_type: match self.filter {
+                Filter::Box => shader_type::FILTER_BOX,
+                Filter::Gaussian => shader_type::FILTER_GAUSSIAN,
+                Filter::Mitchell => shader_type::FILTER_MITCHELL,
+                Filter::BlackmanHarris => shader_type::FILTER_BLACKMAN_HARRIS,
+                Filter::Lanczos => shader_type::FILTER_LANCZOS,
+            },
+            filter_radius: self.filter_radius,
+            render_scale: render_size.x as f32 / self.display_size.x.max(1) as f32,
+            display_filter: match self.display_filter {
+                DisplayFilter::Nearest => shader_type::DISPLAY_NEAREST,
+                DisplayFilter::Bilinear => shader_type::DISPLAY_BILINEAR,
+                DisplayFilter::Bicubic => shader_type::DISPLAY_BICUBIC,
+            },
+            pixel_offset: tile.offset,
+            image_size: tile.image_size,
+            dispatch_offset,
+            dispatch_size,
         }
     }
 }
@@ -352,23 +1248,48 @@ impl Default for Param {
         Self {
             camera: CameraParam::default(),
             display_size: cgmath::Vector2::new(1, 1),
+            render_scale: 1.0,
+            display_filter: DisplayFilter::Bilinear,
+            tile: None,
+            region: None,
+            kernel: Kernel::Megakernel,
             hit_algorithm: HitAlgorithm::BVH,
+            bvh_builder: BvhBuilder::Cpu,
+            bvh_refit: true,
+            bvh_rebuild_ratio: 1.5,
             shading_algorithm: ShadingAlgorithm::Smooth,
+            sampler: Sampler::Sobol,
+            filter: Filter::Box,
+            filter_radius: 0.5,
             max_sample: 256,
             max_bounce: 8,
+            noise_threshold: 0.0,
+            time_budget: 0.0,
+            samples_per_dispatch: 1,
+            target_frame_time: 0.0,
+            reproject: false,
         }
     }
 }
 
 impl CameraParam {
-    pub fn as_shader_type(&self, aspect_ratio: f32) -> shader_type::Camera {
-        let rot_matrix = cgmath::Matrix3::from_angle_y(cgmath::Deg(self.yaw))
-            * cgmath::Matrix3::from_angle_x(cgmath::Deg(self.pitch));
-        let w = rot_matrix * cgmath::vec3(0.0, 0.0, 1.0);
-        let u = rot_matrix * cgmath::vec3(1.0, 0.0, 0.0);
-        let v = rot_matrix * cgmath::vec3(0.0, 1.0, 0.0);
-
-        let fov = self.fov.to_radians();
+    // window is the minimum and the size of the part of the image to render in
+    // texture coordinates
+    pub fn as_shader_type(
+        &self,
+        aspect_ratio: f32,
+        window: (cgmath::Vector2<f32>, cgmath::Vector2<f32>),
+    ) -> shader_type::Camera {
+        let w = -self.forward();
+        let u = self.right();
+        let v = self.up();
+
+        let fov = self.effective_fov(aspect_ratio).to_radians();
+        let lens_radius = if self.use_physical {
+            self.physical.lens_radius()
+        } else {
+            self.aperture / 2.0
+        };
         let height = self.focus_distance * (fov / 2.0).tan();
         let width = height * aspect_ratio;
 
@@ -383,7 +1304,220 @@ impl CameraParam {
             vx: (2.0 * width * u),
             vy: (-2.0 * height * v),
 
-            lens_radius: self.aperture / 2.0,
+            lens_radius,
+            shutter: cgmath::vec2(self.shutter_open, self.shutter_close),
+
+            aperture_shape: match self.aperture_shape {
+                ApertureShape::Circle => shader_type::APERTURE_CIRCLE,
+                ApertureShape::Polygon => shader_type::APERTURE_POLYGON,
+                ApertureShape::Mask => shader_type::APERTURE_MASK,
+            },
+            aperture_blades: self.aperture_blades,
+            aperture_rotation: self.aperture_rotation.to_radians(),
+            cat_eye: self.cat_eye,
+
+            forward: -w,
+            projection: match self.projection {
+                Projection::Perspective => shader_type::PROJ_PERSPECTIVE,
+                Projection::Orthographic => shader_type::PROJ_ORTHOGRAPHIC,
+                Projection::Fisheye => shader_type::PROJ_FISHEYE,
+                Projection::Equirectangular => shader_type::PROJ_EQUIRECTANGULAR,
+                Projection::Cylindrical => shader_type::PROJ_CYLINDRICAL,
+            },
+            fov,
+            aspect_ratio,
+            focus_distance: self.focus_distance,
+            exposure: self.exposure(),
+            window_min: window.0,
+            window_size: window.1,
+        }
+    }
+}
+
+impl CameraParam {
+    fn rotation(&self) -> cgmath::Matrix3<f32> {
+        cgmath::Matrix3::from_angle_y(cgmath::Deg(self.yaw))
+            * cgmath::Matrix3::from_angle_x(cgmath::Deg(self.pitch))
+            * cgmath::Matrix3::from_angle_z(cgmath::Deg(self.roll))
+    }
+
+    pub fn forward(&self) -> cgmath::Vector3<f32> {
+        self.rotation() * -cgmath::Vector3::unit_z()
+    }
+
+    pub fn right(&self) -> cgmath::Vector3<f32> {
+        self.rotation() * cgmath::Vector3::unit_x()
+    }
+
+    pub fn up(&self) -> cgmath::Vector3<f32> {
+        self.rotation() * cgmath::Vector3::unit_y()
+    }
+
+    // point in focus at the center of the image
+    pub fn target(&self) -> cgmath::Vector3<f32> {
+        self.position + self.focus_distance * self.forward()
+    }
+
+    // vertical field of view in degrees, either set directly or derived from
+    // the physical camera
+    pub fn effective_fov(&self, aspect_ratio: f32) -> f32 {
+        if self.use_physical {
+            self.physical.fov(aspect_ratio)
+        } else {
+            self.fov
+        }
+    }
+
+    // multiplier of the radiance, only the physical camera has an exposure
+    pub fn exposure(&self) -> f32 {
+        if self.use_physical {
+            self.physical.exposure()
+        } else {
+            1.0
+        }
+    }
+
+    // orients the camera from position towards target, with up giving the
+    // roll, and focuses on the target
+    pub fn look_at(
+        &mut self,
+        position: cgmath::Vector3<f32>,
+        target: cgmath::Vector3<f32>,
+        up: cgmath::Vector3<f32>,
+    ) {
+        let offset = target - position;
+        let forward = offset.normalize();
+
+        self.position = position;
+        self.focus_distance = offset.magnitude();
+        self.pitch = forward.y.clamp(-1.0, 1.0).asin().to_degrees();
+        self.yaw = (-forward.x).atan2(-forward.z).to_degrees();
+        self.roll = 0.0;
+
+        let right = self.right();
+        let up_unrolled = self.up();
+        self.roll = (-up.dot(right)).atan2(up.dot(up_unrolled)).to_degrees();
+    }
+
+    // moves the camera back along its viewing direction until the bounding
+    // sphere of the box fits in the image
+    pub fn frame(
+        &mut self,
+        min: cgmath::Vector3<f32>,
+        max: cgmath::Vector3<f32>,
+        aspect_ratio: f32,
+    ) {
+        let center = (min + max) / 2.0;
+        let radius = (max - min).magnitude() / 2.0;
+
+        let fov_y = self.effective_fov(aspect_ratio).to_radians();
+        let fov_x = 2.0 * ((fov_y / 2.0).tan() * aspect_ratio).atan();
+        let half_fov = (fov_x.min(fov_y) / 2.0).min(std::f32::consts::FRAC_PI_2);
+        let distance = radius / half_fov.sin();
+
+        self.position = center - distance * self.forward();
+        self.focus_distance = distance;
+    }
+
+    // interpolates the continuous parameters, angles along the shortest way,
+    // while the discrete ones switch to other's halfway
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        let lerp_angle = |a: f32, b: f32| a + ((b - a + 180.0).rem_euclid(360.0) - 180.0) * t;
+
+        let mut result = if t < 0.5 { self.clone() } else { other.clone() };
+        result.position = self.position + (other.position - self.position) * t;
+        result.yaw = lerp_angle(self.yaw, other.yaw);
+        result.pitch = lerp(self.pitch, other.pitch);
+        result.roll = lerp_angle(self.roll, other.roll);
+        result.fov = lerp(self.fov, other.fov);
+        result.aperture = lerp(self.aperture, other.aperture);
+        result.focus_distance = lerp(self.focus_distance, other.focus_distance);
+        result.physical.focal_length =
+            lerp(self.physical.focal_length, other.physical.focal_length);
+        result.physical.f_stop = lerp(self.physical.f_stop, other.physical.f_stop);
+
+        result
+    }
+}
+
+impl PhysicalCamera {
+    // EV100 exposed such that a radiance of 1 (the sky) maps to 1, which
+    // follows the sunny 16 rule (f/16, 1/100s at ISO 100) for daylight scenes
+    const REFERENCE_EV100: f32 = 14.643856;
+
+    // vertical field of view in degrees
+    pub fn fov(&self, aspect_ratio: f32) -> f32 {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        (2.0 * (sensor_height / (2.0 * self.focal_length)).atan()).to_degrees()
+    }
+
+    // in meters
+    pub fn lens_radius(&self) -> f32 {
+        self.focal_length / self.f_stop / 2.0 / 1000.0
+    }
+
+    pub fn ev100(&self) -> f32 {
+        (self.f_stop * self.f_stop / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    // multiplier applied to the radiance, one stop less of EV100 doubles it
+    pub fn exposure(&self) -> f32 {
+        (Self::REFERENCE_EV100 - self.ev100()).exp2()
+    }
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> Self {
+        Self {
+            sensor_width: 36.0,
+            focal_length: 50.0,
+            f_stop: 16.0,
+            shutter_speed: 1.0 / 100.0,
+            iso: 100.0,
+        }
+    }
+}
+
+impl Tile {
+    // tiles of at most tile_size covering the image, row by row
+    pub fn grid(image_size: cgmath::Vector2<u32>, tile_size: u32) -> Vec<Tile> {
+        let tile_size = tile_size.max(1);
+        let mut tiles = Vec::new();
+        for y in (0..image_size.y).step_by(tile_size as usize) {
+            for x in (0..image_size.x).step_by(tile_size as usize) {
+                tiles.push(Tile {
+                    image_size,
+                    offset: cgmath::vec2(x, y),
+                    size: cgmath::vec2(
+                        tile_size.min(image_size.x - x),
+                        tile_size.min(image_size.y - y),
+                    ),
+                });
+            }
+        }
+        tiles
+    }
+}
+
+impl Filter {
+    // radius the filter is commonly used with
+    pub fn default_radius(&self) -> f32 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell | Filter::BlackmanHarris => 2.0,
+            Filter::Lanczos => 3.0,
+        }
+    }
+}
+
+impl Projection {
+    // fisheye lenses can see behind the camera
+    pub fn max_fov(&self) -> f32 {
+        match self {
+            Projection::Fisheye => 360.0,
+            _ => 120.0,
         }
     }
 }
@@ -394,13 +1528,38 @@ impl Default for CameraParam {
             position: cgmath::Vector3::new(13.0, 2.0, 3.0),
             yaw: 80.0,
             pitch: -5.0,
+            roll: 0.0,
+            projection: Projection::Perspective,
+            use_physical: false,
+            physical: PhysicalCamera::default(),
             fov: 20.0,
             aperture: 0.1,
+            aperture_shape: ApertureShape::Circle,
+            aperture_blades: 6,
+            aperture_rotation: 0.0,
+            cat_eye: 0.0,
             focus_distance: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
         }
     }
 }
 
+// in bytes
+fn frame_buffer_size(display_size: cgmath::Vector2<u32>) -> u64 {
+    display_size.x.max(1) as u64 * display_size.y.max(1) as u64 * PIXEL_SIZE as u64
+}
+
+// the copy of the frame buffer to reproject from is only allocated while
+// reprojection is enabled
+fn history_size(param: &Param) -> u64 {
+    if param.reproject {
+        frame_buffer_size(param.render_size())
+    } else {
+        PIXEL_SIZE as u64
+    }
+}
+
 fn create_bind_group(
     device: &wgpu::Device,
     buffers: &[&dyn buffer::Layout],
@@ -417,16 +1576,82 @@ fn create_bind_group(
         label: bind_group_layout_label.as_deref(),
     });
 
+    let bind_group = create_bind_group_with_layout(device, &bind_group_layout, buffers, label);
+
+    (bind_group_layout, bind_group)
+}
+
+fn create_bind_group_with_layout(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffers: &[&dyn buffer::Layout],
+    label: Option<&str>,
+) -> wgpu::BindGroup {
     let bind_group_label = label.map(|l| format!("{l} Bind Group"));
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &bind_group_layout,
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
         entries: &buffers
             .iter()
             .enumerate()
             .map(|(i, buffer)| buffer.binding(i as u32))
             .collect::<Vec<_>>(),
         label: bind_group_label.as_deref(),
-    });
+    })
+}
 
-    (bind_group_layout, bind_group)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        for (image_size, tile_size) in [
+            (cgmath::vec2(1000, 700), 256),
+            (cgmath::vec2(512, 512), 256),
+            (cgmath::vec2(100, 50), 256),
+            (cgmath::vec2(3, 2), 0),
+        ] {
+            let mut covered = vec![0; (image_size.x * image_size.y) as usize];
+            for tile in Tile::grid(image_size, tile_size) {
+                assert_eq!(tile.image_size, image_size);
+                for y in tile.offset.y..tile.offset.y + tile.size.y {
+                    for x in tile.offset.x..tile.offset.x + tile.size.x {
+                        covered[(x + y * image_size.x) as usize] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&count| count == 1));
+        }
+    }
+
+    #[test]
+    fn edge_tiles_are_cut_to_the_image() {
+        let tiles = Tile::grid(cgmath::vec2(1000, 700), 256);
+        assert_eq!(tiles.len(), 4 * 3);
+
+        // row by row, the last column and row taking what is left
+        assert_eq!(tiles[0].size, cgmath::vec2(256, 256));
+        assert_eq!(tiles[3].offset, cgmath::vec2(768, 0));
+        assert_eq!(tiles[3].size, cgmath::vec2(232, 256));
+        assert_eq!(tiles[4].offset, cgmath::vec2(0, 256));
+        assert_eq!(tiles[11].offset, cgmath::vec2(768, 512));
+        assert_eq!(tiles[11].size, cgmath::vec2(232, 188));
+    }
+
+    #[test]
+    fn tile_is_rendered_whole_at_its_size() {
+        let tile = Tile::grid(cgmath::vec2(1000, 700), 256)[11];
+        let param = Param {
+            tile: Some(tile),
+            region: Some((cgmath::vec2(10, 10), cgmath::vec2(20, 20))),
+            ..Default::default()
+        };
+
+        assert_eq!(param.render_size(), tile.size);
+        assert_eq!(param.dispatch_region(), (cgmath::vec2(0, 0), tile.size));
+
+        let shader_param = param.as_shader_type();
+        assert_eq!(shader_param.pixel_offset, tile.offset);
+        assert_eq!(shader_param.image_size, tile.image_size);
+    }
 }
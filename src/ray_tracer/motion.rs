@@ -0,0 +1,85 @@
+use cgmath::SquareMatrix;
+
+use super::{object::AABB, shader_type};
+
+// rigid (or affine) motion of an object over the shutter interval, given as
+// keyframed transforms applied on top of the object's own placement, keyframe
+// times are within [0, 1] and transforms are linearly interpolated in between
+#[derive(Debug, Clone)]
+pub struct Motion {
+    keyframes: Vec<(f32, cgmath::Matrix4<f32>)>,
+}
+
+#[allow(unused)]
+impl Motion {
+    pub fn linear(start: cgmath::Matrix4<f32>, end: cgmath::Matrix4<f32>) -> Self {
+        Self {
+            keyframes: vec![(0.0, start), (1.0, end)],
+        }
+    }
+
+    pub fn translation(offset: cgmath::Vector3<f32>) -> Self {
+        Self::linear(
+            cgmath::Matrix4::identity(),
+            cgmath::Matrix4::from_translation(offset),
+        )
+    }
+
+    pub fn keyframed(mut keyframes: Vec<(f32, cgmath::Matrix4<f32>)>) -> Self {
+        assert!(
+            !keyframes.is_empty() && keyframes.len() <= shader_type::MAX_KEYFRAME,
+            "Expect 1 to {} keyframes",
+            shader_type::MAX_KEYFRAME
+        );
+        keyframes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        Self { keyframes }
+    }
+
+    // bounding boxes at the start and the end of the shutter interval, such that
+    // their interpolation bounds the object at any time in between
+    pub fn bounds(&self, bbox: &AABB) -> (AABB, AABB) {
+        let transform = |matrix: &cgmath::Matrix4<f32>| {
+            let linear = cgmath::Matrix3::from_cols(
+                matrix.x.truncate(),
+                matrix.y.truncate(),
+                matrix.z.truncate(),
+            );
+            let bbox = bbox.transform(&linear);
+            let offset = matrix.w.truncate();
+            AABB::new(bbox.min + offset, bbox.max + offset)
+        };
+
+        match self.keyframes.as_slice() {
+            // a point moving linearly between two keyframes stays within the
+            // interpolated box
+            [(t0, start), (t1, end)] if *t0 == 0.0 && *t1 == 1.0 => {
+                (transform(start), transform(end))
+            }
+            keyframes => {
+                let bbox = keyframes
+                    .iter()
+                    .skip(1)
+                    .fold(transform(&keyframes[0].1), |bbox, (_, matrix)| {
+                        bbox.union(&transform(matrix))
+                    });
+                (bbox.clone(), bbox)
+            }
+        }
+    }
+
+    pub fn as_shader_type(&self) -> shader_type::Motion {
+        let mut times = [0.0; shader_type::MAX_KEYFRAME];
+        let mut transforms = [cgmath::Matrix4::identity(); shader_type::MAX_KEYFRAME];
+        for (i, (time, transform)) in self.keyframes.iter().enumerate() {
+            times[i] = *time;
+            transforms[i] = *transform;
+        }
+
+        shader_type::Motion {
+            num_keyframe: self.keyframes.len() as u32,
+            times,
+            transforms,
+        }
+    }
+}
@@ -1,15 +1,41 @@
-use cgmath::{Deg, EuclideanSpace, SquareMatrix, Transform};
+use cgmath::{Deg, EuclideanSpace, InnerSpace, SquareMatrix, Transform};
 
-use super::{shader_type, util};
+use super::{
+    csg::CsgPrim,
+    motion::Motion,
+    sdf::{self, Sdf},
+    shader_type, util,
+};
 
-pub trait Object {
+// objects are kept by the ray tracer, which is shared with the render thread
+pub trait Object: Send + Sync {
     fn as_prims(&self) -> Vec<Box<dyn ObjPrim>>;
     fn material(&self) -> &Material;
+
+    fn motion(&self) -> Option<&Motion> {
+        None
+    }
+
+    // whether the object only surrounds the scene, like a huge sphere used as
+    // the ground, so that it is left out of the bounds navigation is scaled to
+    fn backdrop(&self) -> bool {
+        false
+    }
 }
 
 pub trait ObjPrim {
     fn as_shader_type(&self, mat_idx: u32) -> shader_type::Object;
     fn bbox(&self) -> &AABB;
+
+    // distance field of the primitive, which is compiled into the shader
+    fn sdf(&self) -> Option<&Sdf> {
+        None
+    }
+
+    // operands of the primitive if it is a CSG node
+    fn csg(&self) -> Option<&CsgPrim> {
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -20,7 +46,9 @@ pub struct AABB {
 
 #[derive(Debug, Clone)]
 pub struct BVHNode {
+    // bounding boxes at the start and the end of the shutter interval
     pub bbox: AABB,
+    pub bbox_end: AABB,
     pub left_idx: i32,
     pub right_idx: i32,
     pub object_idx: i32,
@@ -32,12 +60,45 @@ pub struct Mesh {
     normals: Vec<cgmath::Vector3<f32>>,
     indices: Vec<(usize, usize)>,
     transform: cgmath::Matrix4<f32>,
+    motion: Option<Motion>,
     material: Material,
 }
 
 #[derive(Debug, Clone)]
 pub struct Sphere {
     prim: SpherePrim,
+    motion: Option<Motion>,
+    material: Material,
+    backdrop: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Quad {
+    prim: QuadPrim,
+    material: Material,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cuboid {
+    prim: CuboidPrim,
+    material: Material,
+}
+
+#[derive(Debug, Clone)]
+pub struct Disk {
+    prim: DiskPrim,
+    material: Material,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    prim: CylinderPrim,
+    material: Material,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cone {
+    prim: ConePrim,
     material: Material,
 }
 
@@ -55,6 +116,47 @@ struct SpherePrim {
     pub bbox: AABB,
 }
 
+#[derive(Debug, Clone)]
+struct QuadPrim {
+    pub q: cgmath::Vector3<f32>,
+    pub u: cgmath::Vector3<f32>,
+    pub v: cgmath::Vector3<f32>,
+    pub bbox: AABB,
+}
+
+#[derive(Debug, Clone)]
+struct CuboidPrim {
+    pub center: cgmath::Vector3<f32>,
+    // box edges from the center to the faces, i.e. half extents along each
+    // (possibly rotated) axis
+    pub axes: [cgmath::Vector3<f32>; 3],
+    pub bbox: AABB,
+}
+
+#[derive(Debug, Clone)]
+struct DiskPrim {
+    pub center: cgmath::Vector3<f32>,
+    pub normal: cgmath::Vector3<f32>,
+    pub radius: f32,
+    pub bbox: AABB,
+}
+
+#[derive(Debug, Clone)]
+struct CylinderPrim {
+    pub base: cgmath::Vector3<f32>,
+    pub top: cgmath::Vector3<f32>,
+    pub radius: f32,
+    pub bbox: AABB,
+}
+
+#[derive(Debug, Clone)]
+struct ConePrim {
+    pub apex: cgmath::Vector3<f32>,
+    pub base: cgmath::Vector3<f32>,
+    pub radius: f32,
+    pub bbox: AABB,
+}
+
 #[derive(Debug, Clone)]
 pub enum Material {
     Diffuse {
@@ -78,6 +180,14 @@ impl AABB {
         }
     }
 
+    // box containing nothing, the identity of union
+    pub fn empty() -> Self {
+        Self {
+            min: cgmath::vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: cgmath::vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
     pub fn union(&self, other: &Self) -> Self {
         Self {
             min: cgmath::vec3(
@@ -93,6 +203,53 @@ impl AABB {
         }
     }
 
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            min: cgmath::vec3(
+                self.min.x.max(other.min.x),
+                self.min.y.max(other.min.y),
+                self.min.z.max(other.min.z),
+            ),
+            max: cgmath::vec3(
+                self.max.x.min(other.max.x),
+                self.max.y.min(other.max.y),
+                self.max.z.min(other.max.z),
+            ),
+        }
+    }
+
+    // bounding box of this box after a linear transform
+    pub fn transform(&self, matrix: &cgmath::Matrix3<f32>) -> Self {
+        let center = matrix * (self.min + self.max) / 2.0;
+        let half = (self.max - self.min) / 2.0;
+        let extent = cgmath::vec3(
+            matrix.x.x.abs() * half.x + matrix.y.x.abs() * half.y + matrix.z.x.abs() * half.z,
+            matrix.x.y.abs() * half.x + matrix.y.y.abs() * half.y + matrix.z.y.abs() * half.z,
+            matrix.x.z.abs() * half.x + matrix.y.z.abs() * half.y + matrix.z.z.abs() * half.z,
+        );
+        Self::new(center - extent, center + extent)
+    }
+
+    pub fn pad(&self, delta: f32) -> Self {
+        let delta = cgmath::vec3(delta, delta, delta);
+        Self {
+            min: self.min - delta,
+            max: self.max + delta,
+        }
+    }
+
+    // bounding box of a disk of the given radius, the extent along each axis
+    // is radius * sin(angle between the axis and the normal)
+    fn disk(center: cgmath::Vector3<f32>, normal: cgmath::Vector3<f32>, radius: f32) -> Self {
+        let n = normal.normalize();
+        let extent = cgmath::vec3(
+            radius * (1.0 - n.x * n.x).max(0.0).sqrt(),
+            radius * (1.0 - n.y * n.y).max(0.0).sqrt(),
+            radius * (1.0 - n.z * n.z).max(0.0).sqrt(),
+        );
+        Self::new(center - extent, center + extent)
+    }
+
     pub fn as_shader_type(&self) -> shader_type::AABB {
         shader_type::AABB {
             min: self.min,
@@ -114,6 +271,7 @@ impl BVHNode {
     pub fn as_shader_type(&self) -> shader_type::BVHNode {
         shader_type::BVHNode {
             bbox: self.bbox.as_shader_type(),
+            bbox_end: self.bbox_end.as_shader_type(),
             left_idx: self.left_idx,
             right_idx: self.right_idx,
             object_idx: self.object_idx,
@@ -125,6 +283,7 @@ impl Default for BVHNode {
     fn default() -> Self {
         Self {
             bbox: AABB::default(),
+            bbox_end: AABB::default(),
             left_idx: -1,
             right_idx: -1,
             object_idx: -1,
@@ -178,6 +337,7 @@ impl Mesh {
             normals,
             indices,
             transform: cgmath::Matrix4::identity(),
+            motion: None,
             material,
         }
     }
@@ -200,6 +360,19 @@ impl Mesh {
         self
     }
 
+    pub fn with_motion(mut self, motion: Motion) -> Self {
+        self.motion = Some(motion);
+
+        self
+    }
+
+    // moves linearly from the current transform at the start of the shutter
+    // interval to the given transform at the end
+    pub fn moving_to(self, end_transform: cgmath::Matrix4<f32>) -> Self {
+        let delta = end_transform * self.transform.invert().unwrap();
+        self.with_motion(Motion::linear(cgmath::Matrix4::identity(), delta))
+    }
+
     fn transform_point(&self, v: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
         self.transform
             .transform_point(cgmath::point3(v.x, v.y, v.z))
@@ -232,6 +405,10 @@ impl Object for Mesh {
     fn material(&self) -> &Material {
         &self.material
     }
+
+    fn motion(&self) -> Option<&Motion> {
+        self.motion.as_ref()
+    }
 }
 
 impl Sphere {
@@ -246,9 +423,37 @@ impl Sphere {
                 radius,
                 bbox,
             },
+            motion: None,
             material,
+            backdrop: false,
         }
     }
+
+    // sphere moving linearly from center_start at the start of the shutter
+    // interval to center_end at the end
+    #[allow(unused)]
+    pub fn new_moving(
+        center_start: cgmath::Vector3<f32>,
+        center_end: cgmath::Vector3<f32>,
+        radius: f32,
+        material: Material,
+    ) -> Self {
+        Self::new(center_start, radius, material)
+            .with_motion(Motion::translation(center_end - center_start))
+    }
+
+    #[allow(unused)]
+    pub fn with_motion(mut self, motion: Motion) -> Self {
+        self.motion = Some(motion);
+
+        self
+    }
+
+    pub fn with_backdrop(mut self) -> Self {
+        self.backdrop = true;
+
+        self
+    }
 }
 
 impl Object for Sphere {
@@ -259,6 +464,202 @@ impl Object for Sphere {
     fn material(&self) -> &Material {
         &self.material
     }
+
+    fn motion(&self) -> Option<&Motion> {
+        self.motion.as_ref()
+    }
+
+    fn backdrop(&self) -> bool {
+        self.backdrop
+    }
+}
+
+#[allow(unused)]
+impl Quad {
+    // parallelogram spanned by the edges u and v from the corner q, the normal
+    // follows the right-hand rule of u x v
+    pub fn new(
+        q: cgmath::Vector3<f32>,
+        u: cgmath::Vector3<f32>,
+        v: cgmath::Vector3<f32>,
+        material: Material,
+    ) -> Self {
+        let bbox = AABB::new(q, q + u + v)
+            .union(&AABB::new(q + u, q + v))
+            .pad(0.0001);
+        Self {
+            prim: QuadPrim { q, u, v, bbox },
+            material,
+        }
+    }
+}
+
+impl Object for Quad {
+    fn as_prims(&self) -> Vec<Box<dyn ObjPrim>> {
+        vec![Box::new(self.prim.clone())]
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+#[allow(unused)]
+impl Cuboid {
+    // axis-aligned box with opposite corners p and q, use `rotate` and
+    // `translate` for oriented boxes
+    pub fn new(p: cgmath::Vector3<f32>, q: cgmath::Vector3<f32>, material: Material) -> Self {
+        let half = (q - p) / 2.0;
+        let half = cgmath::vec3(half.x.abs(), half.y.abs(), half.z.abs());
+        let mut cuboid = Self {
+            prim: CuboidPrim {
+                center: (p + q) / 2.0,
+                axes: [
+                    cgmath::vec3(half.x, 0.0, 0.0),
+                    cgmath::vec3(0.0, half.y, 0.0),
+                    cgmath::vec3(0.0, 0.0, half.z),
+                ],
+                bbox: AABB::default(),
+            },
+            material,
+        };
+        cuboid.update_bbox();
+
+        cuboid
+    }
+
+    pub fn translate(mut self, translation: cgmath::Vector3<f32>) -> Self {
+        self.prim.center += translation;
+        self.update_bbox();
+
+        self
+    }
+
+    pub fn rotate(mut self, rotation: cgmath::Euler<Deg<f32>>) -> Self {
+        let rotation = cgmath::Matrix3::from(rotation);
+        self.prim.center = rotation * self.prim.center;
+        self.prim.axes = self.prim.axes.map(|axis| rotation * axis);
+        self.update_bbox();
+
+        self
+    }
+
+    fn update_bbox(&mut self) {
+        let [a, b, c] = self.prim.axes;
+        let extent = cgmath::vec3(
+            a.x.abs() + b.x.abs() + c.x.abs(),
+            a.y.abs() + b.y.abs() + c.y.abs(),
+            a.z.abs() + b.z.abs() + c.z.abs(),
+        );
+        self.prim.bbox = AABB::new(self.prim.center - extent, self.prim.center + extent);
+    }
+}
+
+impl Object for Cuboid {
+    fn as_prims(&self) -> Vec<Box<dyn ObjPrim>> {
+        vec![Box::new(self.prim.clone())]
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+#[allow(unused)]
+impl Disk {
+    pub fn new(
+        center: cgmath::Vector3<f32>,
+        normal: cgmath::Vector3<f32>,
+        radius: f32,
+        material: Material,
+    ) -> Self {
+        Self {
+            prim: DiskPrim {
+                center,
+                normal: normal.normalize(),
+                radius,
+                bbox: AABB::disk(center, normal, radius).pad(0.0001),
+            },
+            material,
+        }
+    }
+}
+
+impl Object for Disk {
+    fn as_prims(&self) -> Vec<Box<dyn ObjPrim>> {
+        vec![Box::new(self.prim.clone())]
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+#[allow(unused)]
+impl Cylinder {
+    // cylinder capped at both ends, with the axis going from the center of the
+    // base to the center of the top
+    pub fn new(
+        base: cgmath::Vector3<f32>,
+        top: cgmath::Vector3<f32>,
+        radius: f32,
+        material: Material,
+    ) -> Self {
+        let axis = top - base;
+        let bbox = AABB::disk(base, axis, radius).union(&AABB::disk(top, axis, radius));
+        Self {
+            prim: CylinderPrim {
+                base,
+                top,
+                radius,
+                bbox,
+            },
+            material,
+        }
+    }
+}
+
+impl Object for Cylinder {
+    fn as_prims(&self) -> Vec<Box<dyn ObjPrim>> {
+        vec![Box::new(self.prim.clone())]
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+#[allow(unused)]
+impl Cone {
+    // cone capped at the base, with the axis going from the center of the base
+    // to the apex
+    pub fn new(
+        base: cgmath::Vector3<f32>,
+        apex: cgmath::Vector3<f32>,
+        radius: f32,
+        material: Material,
+    ) -> Self {
+        let bbox = AABB::disk(base, apex - base, radius).union(&AABB::new(apex, apex));
+        Self {
+            prim: ConePrim {
+                apex,
+                base,
+                radius,
+                bbox,
+            },
+            material,
+        }
+    }
+}
+
+impl Object for Cone {
+    fn as_prims(&self) -> Vec<Box<dyn ObjPrim>> {
+        vec![Box::new(self.prim.clone())]
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
 }
 
 impl ObjPrim for Triangle {
@@ -266,6 +667,8 @@ impl ObjPrim for Triangle {
         shader_type::Object {
             obj_type: shader_type::OBJ_TRIANGLE,
             mat_idx,
+            data_idx: 0,
+            motion_idx: -1,
             v: self.v,
             n: self.n,
         }
@@ -281,6 +684,8 @@ impl ObjPrim for SpherePrim {
         shader_type::Object {
             obj_type: shader_type::OBJ_SPHERE,
             mat_idx,
+            data_idx: 0,
+            motion_idx: -1,
             v: [
                 self.center,
                 cgmath::vec3(self.radius, 0.0, 0.0),
@@ -299,6 +704,115 @@ impl ObjPrim for SpherePrim {
     }
 }
 
+impl ObjPrim for QuadPrim {
+    fn as_shader_type(&self, mat_idx: u32) -> shader_type::Object {
+        shader_type::Object {
+            obj_type: shader_type::OBJ_QUAD,
+            mat_idx,
+            data_idx: 0,
+            motion_idx: -1,
+            v: [self.q, self.u, self.v],
+            n: [
+                self.u.cross(self.v).normalize(),
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+            ],
+        }
+    }
+
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+impl ObjPrim for CuboidPrim {
+    fn as_shader_type(&self, mat_idx: u32) -> shader_type::Object {
+        shader_type::Object {
+            obj_type: shader_type::OBJ_BOX,
+            mat_idx,
+            data_idx: 0,
+            motion_idx: -1,
+            v: [self.center, self.axes[0], self.axes[1]],
+            n: [
+                self.axes[2],
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+            ],
+        }
+    }
+
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+impl ObjPrim for DiskPrim {
+    fn as_shader_type(&self, mat_idx: u32) -> shader_type::Object {
+        shader_type::Object {
+            obj_type: shader_type::OBJ_DISK,
+            mat_idx,
+            data_idx: 0,
+            motion_idx: -1,
+            v: [
+                self.center,
+                cgmath::vec3(self.radius, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+            ],
+            n: [
+                self.normal,
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+            ],
+        }
+    }
+
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+impl ObjPrim for CylinderPrim {
+    fn as_shader_type(&self, mat_idx: u32) -> shader_type::Object {
+        shader_type::Object {
+            obj_type: shader_type::OBJ_CYLINDER,
+            mat_idx,
+            data_idx: 0,
+            motion_idx: -1,
+            v: [self.base, self.top, cgmath::vec3(self.radius, 0.0, 0.0)],
+            n: [
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+            ],
+        }
+    }
+
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
+impl ObjPrim for ConePrim {
+    fn as_shader_type(&self, mat_idx: u32) -> shader_type::Object {
+        shader_type::Object {
+            obj_type: shader_type::OBJ_CONE,
+            mat_idx,
+            data_idx: 0,
+            motion_idx: -1,
+            v: [self.base, self.apex, cgmath::vec3(self.radius, 0.0, 0.0)],
+            n: [
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+            ],
+        }
+    }
+
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
+}
+
 impl Material {
     pub fn as_shader_type(&self) -> shader_type::Material {
         match self {
@@ -321,47 +835,127 @@ impl Material {
     }
 }
 
+// bounds of the primitives, empty if there are none
+pub fn prims_bbox(prims: &[Box<dyn ObjPrim>]) -> AABB {
+    prims
+        .iter()
+        .fold(AABB::empty(), |bbox, p| bbox.union(p.bbox()))
+}
+
+// bounds of the whole scene without its backdrops, unless there is nothing
+// else, primitives with bounds that are not finite are left out, an empty
+// scene is bounded by the origin
+pub fn scene_bbox(objects: &[Box<dyn Object>]) -> AABB {
+    let only_backdrops = objects.iter().all(|object| object.backdrop());
+    objects
+        .iter()
+        .filter(|object| only_backdrops || !object.backdrop())
+        .flat_map(|object| object.as_prims())
+        .map(|p| p.bbox().clone())
+        .filter(|bbox| (0..3).all(|axis| bbox.min[axis].is_finite() && bbox.max[axis].is_finite()))
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or_default()
+}
+
+// the bounding boxes of the top level objects at the start and the end of the
+// shutter interval are left for the BVH to be built over
 pub fn as_shader_types(
-    _objects: &[Box<dyn Object>],
+    _objects: &[Box<dyn Object + '_>],
 ) -> (
-    shader_type::BVH,
+    Vec<(AABB, AABB)>,
     shader_type::Objects,
     shader_type::Materials,
+    shader_type::Motions,
+    String,
 ) {
     let mut primitives = Vec::new();
+    let mut bboxes = Vec::new();
     let mut objects = Vec::new();
     let mut materials = Vec::new();
+    let mut motions = Vec::new();
 
     for object in _objects {
         let prims = object.as_prims();
 
-        objects.extend(
-            prims
-                .iter()
-                .map(|p| p.as_shader_type(materials.len() as u32)),
-        );
+        let motion_idx = match object.motion() {
+            Some(motion) => {
+                motions.push(motion.as_shader_type());
+                motions.len() as i32 - 1
+            }
+            None => -1,
+        };
+
+        bboxes.extend(prims.iter().map(|p| match object.motion() {
+            Some(motion) => motion.bounds(p.bbox()),
+            None => (p.bbox().clone(), p.bbox().clone()),
+        }));
+        objects.extend(prims.iter().map(|p| shader_type::Object {
+            motion_idx,
+            ..p.as_shader_type(materials.len() as u32)
+        }));
         primitives.extend(prims);
         materials.push(object.material().as_shader_type());
     }
 
+    // CSG operands are placed after all top level objects, so that they are
+    // only hit through their CSG node
+    let num_object = objects.len();
+    let mut all_primitives: Vec<&dyn ObjPrim> = primitives.iter().map(|p| p.as_ref()).collect();
+    for (i, prim) in primitives.iter().enumerate() {
+        if let Some(csg) = prim.csg() {
+            let mat_idx = objects[i].mat_idx;
+
+            let a_start = objects.len();
+            objects.extend(csg.a.iter().map(|p| p.as_shader_type(mat_idx)));
+            all_primitives.extend(csg.a.iter().map(|p| p.as_ref()));
+
+            let b_start = objects.len();
+            objects.extend(csg.b.iter().map(|p| p.as_shader_type(mat_idx)));
+            all_primitives.extend(csg.b.iter().map(|p| p.as_ref()));
+
+            objects[i].v[0] = cgmath::vec3(a_start as f32, b_start as f32, objects.len() as f32);
+        }
+    }
+
+    let mut sdfs = Vec::new();
+    for (object, prim) in objects.iter_mut().zip(all_primitives) {
+        if let Some(sdf) = prim.sdf() {
+            object.data_idx = sdfs.len() as u32;
+            sdfs.push(sdf);
+        }
+    }
+    let sdf_source = sdf::as_wgsl(&sdfs);
+
     let objects = shader_type::Objects {
+        num_object: num_object as u32,
         objects,
-        ..Default::default()
     };
     let materials = shader_type::Materials {
         materials,
         ..Default::default()
     };
 
-    (build_bvh(&primitives), objects, materials)
+    // a storage buffer cannot be bound empty
+    if motions.is_empty() {
+        motions.push(Motion::keyframed(vec![(0.0, cgmath::Matrix4::identity())]).as_shader_type());
+    }
+    let motions = shader_type::Motions {
+        motions,
+        ..Default::default()
+    };
+
+    (bboxes, objects, materials, motions, sdf_source)
 }
 
-fn build_bvh(_objects: &[Box<dyn ObjPrim>]) -> shader_type::BVH {
-    fn build(objects: &mut [(usize, &AABB)], result: &mut Vec<BVHNode>) -> usize {
+// builds the BVH over the bounding boxes of the objects at the start and the end
+// of the shutter interval
+pub fn build_bvh(_objects: &[(AABB, AABB)]) -> shader_type::BVH {
+    fn build(objects: &mut [(usize, &AABB, &AABB)], result: &mut Vec<BVHNode>) -> usize {
         if objects.len() == 1 {
-            let (idx, bbox) = objects[0];
+            let (idx, bbox, bbox_end) = objects[0];
             result.push(BVHNode {
                 bbox: bbox.clone(),
+                bbox_end: bbox_end.clone(),
                 object_idx: idx as i32,
                 ..Default::default()
             });
@@ -380,6 +974,7 @@ fn build_bvh(_objects: &[Box<dyn ObjPrim>]) -> shader_type::BVH {
 
         result.push(BVHNode {
             bbox: result[left].bbox.union(&result[right].bbox),
+            bbox_end: result[left].bbox_end.union(&result[right].bbox_end),
             left_idx: left as i32,
             right_idx: right as i32,
             ..Default::default()
@@ -388,10 +983,10 @@ fn build_bvh(_objects: &[Box<dyn ObjPrim>]) -> shader_type::BVH {
         result.len() - 1
     }
 
-    let mut objects: Vec<(usize, &AABB)> = _objects
+    let mut objects: Vec<(usize, &AABB, &AABB)> = _objects
         .iter()
         .enumerate()
-        .map(|(i, obj)| (i, obj.bbox()))
+        .map(|(i, (bbox, bbox_end))| (i, bbox, bbox_end))
         .collect();
     let mut result = vec![BVHNode::default()];
 
@@ -406,3 +1001,58 @@ fn build_bvh(_objects: &[Box<dyn ObjPrim>]) -> shader_type::BVH {
         ..Default::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(center: cgmath::Vector3<f32>, radius: f32) -> Box<dyn Object> {
+        let albedo = cgmath::vec3(0.5, 0.5, 0.5);
+        Box::new(Sphere::new(center, radius, Material::Diffuse { albedo }))
+    }
+
+    #[test]
+    fn scene_bbox_is_the_union_of_the_objects() {
+        let mut objects: Vec<Box<dyn Object>> = (0..40)
+            .map(|i| sphere(cgmath::vec3(i as f32, 0.0, 0.0), 1.0))
+            .collect();
+        objects.push(sphere(cgmath::vec3(0.0, 0.0, 50.0), 1.0));
+
+        let bbox = scene_bbox(&objects);
+        assert_eq!(bbox.min, cgmath::vec3(-1.0, -1.0, -1.0));
+        assert_eq!(bbox.max, cgmath::vec3(40.0, 1.0, 51.0));
+    }
+
+    #[test]
+    fn scene_bbox_leaves_out_backdrops() {
+        let albedo = cgmath::vec3(0.5, 0.5, 0.5);
+        let ground = || -> Box<dyn Object> {
+            let center = cgmath::vec3(0.0, -1000.0, 0.0);
+            Box::new(Sphere::new(center, 1000.0, Material::Diffuse { albedo }).with_backdrop())
+        };
+
+        let objects = vec![ground(), sphere(cgmath::vec3(0.0, 1.0, 0.0), 1.0)];
+        let bbox = scene_bbox(&objects);
+        assert_eq!(bbox.min, cgmath::vec3(-1.0, 0.0, -1.0));
+        assert_eq!(bbox.max, cgmath::vec3(1.0, 2.0, 1.0));
+
+        // objects of any size count if they are not backdrops
+        let objects = vec![sphere(cgmath::vec3(0.0, -1000.0, 0.0), 1000.0)];
+        let bbox = scene_bbox(&objects);
+        assert_eq!(bbox.min, cgmath::vec3(-1000.0, -2000.0, -1000.0));
+
+        // and backdrops do if there is nothing else
+        let bbox = scene_bbox(&[ground()]);
+        assert_eq!(bbox.min, cgmath::vec3(-1000.0, -2000.0, -1000.0));
+    }
+
+    #[test]
+    fn scene_bbox_of_nothing_finite_is_the_origin() {
+        assert_eq!(scene_bbox(&[]).min, cgmath::vec3(0.0, 0.0, 0.0));
+
+        let objects = vec![sphere(cgmath::vec3(f32::NAN, 0.0, 0.0), 1.0)];
+        let bbox = scene_bbox(&objects);
+        assert_eq!(bbox.min, cgmath::vec3(0.0, 0.0, 0.0));
+        assert_eq!(bbox.max, cgmath::vec3(0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,135 @@
+use std::iter;
+
+use wgpu::util::DeviceExt;
+
+use super::{buffer, shader_type};
+use crate::wgpu;
+
+// both are needed for ray queries, and are still experimental in wgpu
+pub const FEATURES: wgpu::Features =
+    wgpu::Features::EXPERIMENTAL_RAY_TRACING_ACCELERATION_STRUCTURE
+        .union(wgpu::Features::EXPERIMENTAL_RAY_QUERY);
+
+const VERTEX_SIZE: u64 = 3 * size_of::<f32>() as u64;
+const IDENTITY: [f32; 12] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
+pub fn is_supported(device: &wgpu::Device) -> bool {
+    device.features().contains(FEATURES)
+}
+
+// acceleration structures of the static triangles among the top level
+// objects, one triangle per object so that the primitive index of a hit is the
+// object index, with the other objects left inactive by NaN vertices
+pub struct AccelerationStructure {
+    // the instance keeps the bottom level structure alive
+    package: wgpu::TlasPackage,
+}
+
+impl AccelerationStructure {
+    // None if the device has no ray queries
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        objects: &shader_type::Objects,
+    ) -> Option<Self> {
+        if !is_supported(device) {
+            return None;
+        }
+
+        let mut triangles: Vec<[cgmath::Vector3<f32>; 3]> = objects.objects
+            [..objects.num_object as usize]
+            .iter()
+            .map(|object| {
+                if object.obj_type == shader_type::OBJ_TRIANGLE && object.motion_idx < 0 {
+                    object.v
+                } else {
+                    [cgmath::vec3(f32::NAN, f32::NAN, f32::NAN); 3]
+                }
+            })
+            .collect();
+        if triangles.is_empty() {
+            triangles.push([cgmath::vec3(f32::NAN, f32::NAN, f32::NAN); 3]);
+        }
+        let vertices: Vec<u8> = triangles
+            .iter()
+            .flatten()
+            .flat_map(|v| [v.x, v.y, v.z])
+            .flat_map(f32::to_ne_bytes)
+            .collect();
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ray Tracer Acceleration Structure Vertex Buffer"),
+            contents: &vertices,
+            usage: wgpu::BufferUsages::BLAS_INPUT,
+        });
+        let size = wgpu::BlasTriangleGeometrySizeDescriptor {
+            vertex_format: wgpu::VertexFormat::Float32x3,
+            vertex_count: vertices.len() as u32 / VERTEX_SIZE as u32,
+            index_format: None,
+            index_count: None,
+            flags: wgpu::AccelerationStructureGeometryFlags::OPAQUE,
+        };
+
+        let blas = device.create_blas(
+            &wgpu::CreateBlasDescriptor {
+                label: Some("Ray Tracer BLAS"),
+                flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
+                update_mode: wgpu::AccelerationStructureUpdateMode::Build,
+            },
+            wgpu::BlasGeometrySizeDescriptors::Triangles {
+                descriptors: vec![size.clone()],
+            },
+        );
+        let tlas = device.create_tlas(&wgpu::CreateTlasDescriptor {
+            label: Some("Ray Tracer TLAS"),
+            max_instances: 1,
+            flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
+            update_mode: wgpu::AccelerationStructureUpdateMode::Build,
+        });
+        let mut package = wgpu::TlasPackage::new(tlas);
+        package[0] = Some(wgpu::TlasInstance::new(&blas, IDENTITY, 0, 0xff));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Ray Tracer Acceleration Structure Encoder"),
+        });
+        encoder.build_acceleration_structures(
+            iter::once(&wgpu::BlasBuildEntry {
+                blas: &blas,
+                geometry: wgpu::BlasGeometries::TriangleGeometries(vec![
+                    wgpu::BlasTriangleGeometry {
+                        size: &size,
+                        vertex_buffer: &vertex_buffer,
+                        first_vertex: 0,
+                        vertex_stride: VERTEX_SIZE,
+                        index_buffer: None,
+                        first_index: None,
+                        transform_buffer: None,
+                        transform_buffer_offset: None,
+                    },
+                ]),
+            }),
+            iter::once(&package),
+        );
+        queue.submit(Some(encoder.finish()));
+
+        Some(Self { package })
+    }
+}
+
+impl buffer::Layout for AccelerationStructure {
+    fn layout(&self, binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::AccelerationStructure,
+            count: None,
+        }
+    }
+
+    fn binding(&self, binding: u32) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding,
+            resource: self.package.as_binding(),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use super::{object::AABB, shader_type};
+
+// BVH kept with its topology to refit the bounds of its nodes to moved
+// objects, which is much cheaper than a rebuild but loosens the tree as the
+// objects move away from where they were when it was built
+pub struct Refit {
+    bvh: shader_type::BVH,
+    // internal nodes reachable from the root, each after its children
+    order: Vec<usize>,
+    // SAH cost of the tree as built and as last refitted
+    built_cost: f32,
+    cost: f32,
+}
+
+impl Refit {
+    pub fn new(bvh: shader_type::BVH) -> Self {
+        // a preorder reversed visits the children before their parents
+        let mut order = Vec::new();
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = &bvh.nodes[idx];
+            if node.object_idx >= 0 {
+                continue;
+            }
+
+            // internal nodes have both children in either builder
+            order.push(idx);
+            stack.push(node.left_idx as usize);
+            stack.push(node.right_idx as usize);
+        }
+        order.reverse();
+
+        let cost = sah_cost(&bvh);
+        Self {
+            bvh,
+            order,
+            built_cost: cost,
+            cost,
+        }
+    }
+
+    pub fn bvh(&self) -> &shader_type::BVH {
+        &self.bvh
+    }
+
+    // updates the leaves to the bounding boxes of their objects at the start
+    // and the end of the shutter interval, and their ancestors bottom up
+    pub fn refit(&mut self, bboxes: &[(AABB, AABB)]) {
+        let nodes = &mut self.bvh.nodes;
+        for node in nodes.iter_mut().filter(|node| node.object_idx >= 0) {
+            let (bbox, bbox_end) = &bboxes[node.object_idx as usize];
+            node.bbox = bbox.as_shader_type();
+            node.bbox_end = bbox_end.as_shader_type();
+        }
+
+        for &idx in &self.order {
+            let left = &nodes[nodes[idx].left_idx as usize];
+            let right = &nodes[nodes[idx].right_idx as usize];
+            let bbox = union(left.bbox.clone(), right.bbox.clone());
+            let bbox_end = union(left.bbox_end.clone(), right.bbox_end.clone());
+
+            nodes[idx].bbox = bbox;
+            nodes[idx].bbox_end = bbox_end;
+        }
+
+        self.cost = sah_cost(&self.bvh);
+    }
+
+    pub fn degraded(&self, ratio: f32) -> bool {
+        degraded(self.built_cost, self.cost, ratio)
+    }
+}
+
+// whether the SAH cost has grown beyond the ratio to that of the tree as
+// built, so that it is worth rebuilding, never if the ratio is 0, and ratios
+// below 1 are taken as 1 as a refitted tree is never better than rebuilt
+pub fn degraded(built_cost: f32, cost: f32, ratio: f32) -> bool {
+    ratio > 0.0 && cost > built_cost * ratio.max(1.0)
+}
+
+fn union(a: shader_type::AABB, b: shader_type::AABB) -> shader_type::AABB {
+    shader_type::AABB {
+        min: cgmath::vec3(
+            a.min.x.min(b.min.x),
+            a.min.y.min(b.min.y),
+            a.min.z.min(b.min.z),
+        ),
+        max: cgmath::vec3(
+            a.max.x.max(b.max.x),
+            a.max.y.max(b.max.y),
+            a.max.z.max(b.max.z),
+        ),
+    }
+}
+
+// expected nodes visited by a ray through the root, by the surface area
+// heuristic with the costs of traversing a node and intersecting an object
+// taken as equal, over the boxes swept within the shutter interval
+pub fn sah_cost(bvh: &shader_type::BVH) -> f32 {
+    let area = |node: &shader_type::BVHNode| {
+        let bbox = union(node.bbox.clone(), node.bbox_end.clone());
+        let d = bbox.max - bbox.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    };
+
+    let root_area = area(&bvh.nodes[0]).max(f32::MIN_POSITIVE);
+    bvh.nodes.iter().map(area).sum::<f32>() / root_area
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray_tracer::object::build_bvh;
+
+    // in a row along x, which the CPU builder splits in order whichever axis
+    // it picks at random
+    fn bboxes(num_object: usize, spacing: f32) -> Vec<(AABB, AABB)> {
+        (0..num_object)
+            .map(|i| {
+                let p = cgmath::vec3(i as f32 * spacing, 0.0, 0.0);
+                let bbox = AABB::new(p, p + cgmath::vec3(1.0, 1.0, 1.0));
+                let bbox_end = AABB::new(bbox.min, bbox.max + cgmath::vec3(0.0, 0.5, 0.0));
+                (bbox, bbox_end)
+            })
+            .collect()
+    }
+
+    fn same(a: &shader_type::AABB, b: &shader_type::AABB) -> bool {
+        a.min == b.min && a.max == b.max
+    }
+
+    #[test]
+    fn refit_bounds_match_a_rebuild() {
+        let mut refit = Refit::new(build_bvh(&bboxes(50, 2.0)));
+        let moved: Vec<(AABB, AABB)> = bboxes(50, 3.0).into_iter().rev().collect();
+        refit.refit(&moved);
+
+        let rebuilt = build_bvh(&moved);
+        let nodes = &refit.bvh().nodes;
+        assert!(same(&nodes[0].bbox, &rebuilt.nodes[0].bbox));
+        assert!(same(&nodes[0].bbox_end, &rebuilt.nodes[0].bbox_end));
+
+        for node in nodes.iter() {
+            if node.object_idx >= 0 {
+                let (bbox, bbox_end) = &moved[node.object_idx as usize];
+                assert!(same(&node.bbox, &bbox.as_shader_type()));
+                assert!(same(&node.bbox_end, &bbox_end.as_shader_type()));
+            } else if node.left_idx != node.right_idx {
+                let left = &nodes[node.left_idx as usize];
+                let right = &nodes[node.right_idx as usize];
+                assert!(same(
+                    &node.bbox,
+                    &union(left.bbox.clone(), right.bbox.clone())
+                ));
+                let bbox_end = union(left.bbox_end.clone(), right.bbox_end.clone());
+                assert!(same(&node.bbox_end, &bbox_end));
+            }
+        }
+    }
+
+    #[test]
+    fn refit_degrades_as_objects_swap() {
+        let bboxes = bboxes(50, 2.0);
+        let mut refit = Refit::new(build_bvh(&bboxes));
+
+        refit.refit(&bboxes);
+        assert!(!refit.degraded(1.0));
+
+        // each object takes the place of one far away
+        let swapped: Vec<(AABB, AABB)> = (0..bboxes.len())
+            .map(|i| bboxes[i * 17 % bboxes.len()].clone())
+            .collect();
+        refit.refit(&swapped);
+        assert!(refit.degraded(1.5));
+        assert!(!refit.degraded(0.0));
+    }
+
+    #[test]
+    fn ratios_below_one_are_taken_as_one() {
+        assert!(!degraded(10.0, 9.0, 0.5));
+        assert!(!degraded(10.0, 10.0, 0.5));
+        assert!(degraded(10.0, 10.5, 0.5));
+        assert!(!degraded(10.0, 100.0, 0.0));
+    }
+}
@@ -8,13 +8,16 @@ use super::{
 pub fn random_spheres() -> Vec<Box<dyn Object>> {
     let mut objects: Vec<Box<dyn Object>> = Vec::new();
 
-    objects.push(Box::new(Sphere::new(
-        vec3(0.0, -1000.0, 0.0),
-        1000.0,
-        Material::Diffuse {
-            albedo: vec3(0.5, 0.5, 0.5),
-        },
-    )));
+    objects.push(Box::new(
+        Sphere::new(
+            vec3(0.0, -1000.0, 0.0),
+            1000.0,
+            Material::Diffuse {
+                albedo: vec3(0.5, 0.5, 0.5),
+            },
+        )
+        .with_backdrop(),
+    ));
 
     // objects.push(Box::new(Sphere::new(
     //     vec3(4.0, 1.0, 0.0),
@@ -0,0 +1,285 @@
+use cgmath::Deg;
+
+use super::{
+    object::{AABB, Material, ObjPrim, Object},
+    shader_type,
+};
+
+// signed distance field expression tree, compiled to a WGSL expression so that
+// each SDF object gets its own specialized distance function in the shader
+#[derive(Debug, Clone)]
+pub enum Sdf {
+    Sphere {
+        radius: f32,
+    },
+    Box {
+        half_extent: cgmath::Vector3<f32>,
+    },
+    // torus lying in the xz plane
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    Capsule {
+        a: cgmath::Vector3<f32>,
+        b: cgmath::Vector3<f32>,
+        radius: f32,
+    },
+    Translate {
+        offset: cgmath::Vector3<f32>,
+        sdf: Box<Sdf>,
+    },
+    Rotate {
+        rotation: cgmath::Matrix3<f32>,
+        sdf: Box<Sdf>,
+    },
+    Union(Box<Sdf>, Box<Sdf>),
+    Intersection(Box<Sdf>, Box<Sdf>),
+    Subtraction(Box<Sdf>, Box<Sdf>),
+    SmoothUnion {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        k: f32,
+    },
+    // repetition of the child in cells of size period, repeated count times to
+    // each side of the origin along each axis
+    Repeat {
+        period: cgmath::Vector3<f32>,
+        count: cgmath::Vector3<u32>,
+        sdf: Box<Sdf>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct SdfShape {
+    prim: SdfPrim,
+    material: Material,
+}
+
+#[derive(Debug, Clone)]
+struct SdfPrim {
+    pub sdf: Sdf,
+    pub bbox: AABB,
+}
+
+#[allow(unused)]
+impl Sdf {
+    pub fn sphere(radius: f32) -> Self {
+        Self::Sphere { radius }
+    }
+
+    pub fn cuboid(half_extent: cgmath::Vector3<f32>) -> Self {
+        Self::Box { half_extent }
+    }
+
+    pub fn torus(major_radius: f32, minor_radius: f32) -> Self {
+        Self::Torus {
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    pub fn capsule(a: cgmath::Vector3<f32>, b: cgmath::Vector3<f32>, radius: f32) -> Self {
+        Self::Capsule { a, b, radius }
+    }
+
+    pub fn translate(self, offset: cgmath::Vector3<f32>) -> Self {
+        Self::Translate {
+            offset,
+            sdf: Box::new(self),
+        }
+    }
+
+    pub fn rotate(self, rotation: cgmath::Euler<Deg<f32>>) -> Self {
+        Self::Rotate {
+            rotation: cgmath::Matrix3::from(rotation),
+            sdf: Box::new(self),
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self::Union(Box::new(self), Box::new(other))
+    }
+
+    pub fn intersection(self, other: Self) -> Self {
+        Self::Intersection(Box::new(self), Box::new(other))
+    }
+
+    pub fn subtraction(self, other: Self) -> Self {
+        Self::Subtraction(Box::new(self), Box::new(other))
+    }
+
+    pub fn smooth_union(self, other: Self, k: f32) -> Self {
+        Self::SmoothUnion {
+            a: Box::new(self),
+            b: Box::new(other),
+            k,
+        }
+    }
+
+    pub fn repeat(self, period: cgmath::Vector3<f32>, count: cgmath::Vector3<u32>) -> Self {
+        Self::Repeat {
+            period,
+            count,
+            sdf: Box::new(self),
+        }
+    }
+
+    pub fn bbox(&self) -> AABB {
+        match self {
+            Self::Sphere { radius } => AABB::new(
+                -cgmath::vec3(*radius, *radius, *radius),
+                cgmath::vec3(*radius, *radius, *radius),
+            ),
+            Self::Box { half_extent } => AABB::new(-*half_extent, *half_extent),
+            Self::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let r = major_radius + minor_radius;
+                AABB::new(
+                    -cgmath::vec3(r, *minor_radius, r),
+                    cgmath::vec3(r, *minor_radius, r),
+                )
+            }
+            Self::Capsule { a, b, radius } => {
+                let r = cgmath::vec3(*radius, *radius, *radius);
+                AABB::new(a - r, a + r).union(&AABB::new(b - r, b + r))
+            }
+            Self::Translate { offset, sdf } => {
+                let bbox = sdf.bbox();
+                AABB::new(bbox.min + offset, bbox.max + offset)
+            }
+            Self::Rotate { rotation, sdf } => sdf.bbox().transform(rotation),
+            Self::Union(a, b) => a.bbox().union(&b.bbox()),
+            Self::Intersection(a, b) => a.bbox().intersection(&b.bbox()),
+            Self::Subtraction(a, _) => a.bbox(),
+            Self::SmoothUnion { a, b, k } => a.bbox().union(&b.bbox()).pad(*k),
+            Self::Repeat { period, count, sdf } => {
+                let bbox = sdf.bbox();
+                let extent = cgmath::vec3(
+                    period.x * count.x as f32,
+                    period.y * count.y as f32,
+                    period.z * count.z as f32,
+                );
+                AABB::new(bbox.min - extent, bbox.max + extent)
+            }
+        }
+    }
+
+    // WGSL expression of the distance at the point given by the WGSL
+    // expression p
+    pub fn as_wgsl(&self, p: &str) -> String {
+        match self {
+            Self::Sphere { radius } => format!("sdf_sphere({p}, {radius:?})"),
+            Self::Box { half_extent } => format!("sdf_box({p}, {})", vec3_wgsl(half_extent)),
+            Self::Torus {
+                major_radius,
+                minor_radius,
+            } => format!("sdf_torus({p}, vec2<f32>({major_radius:?}, {minor_radius:?}))"),
+            Self::Capsule { a, b, radius } => format!(
+                "sdf_capsule({p}, {}, {}, {radius:?})",
+                vec3_wgsl(a),
+                vec3_wgsl(b)
+            ),
+            Self::Translate { offset, sdf } => {
+                sdf.as_wgsl(&format!("({p} - {})", vec3_wgsl(offset)))
+            }
+            // the inverse of a rotation is its transpose, and v * m in WGSL is
+            // transpose(m) * v
+            Self::Rotate { rotation, sdf } => sdf.as_wgsl(&format!(
+                "({p} * mat3x3<f32>({}, {}, {}))",
+                vec3_wgsl(&rotation.x),
+                vec3_wgsl(&rotation.y),
+                vec3_wgsl(&rotation.z)
+            )),
+            Self::Union(a, b) => format!("min({}, {})", a.as_wgsl(p), b.as_wgsl(p)),
+            Self::Intersection(a, b) => format!("max({}, {})", a.as_wgsl(p), b.as_wgsl(p)),
+            Self::Subtraction(a, b) => format!("max({}, -{})", a.as_wgsl(p), b.as_wgsl(p)),
+            Self::SmoothUnion { a, b, k } => format!(
+                "sdf_smooth_union({}, {}, {k:?})",
+                a.as_wgsl(p),
+                b.as_wgsl(p)
+            ),
+            Self::Repeat { period, count, sdf } => sdf.as_wgsl(&format!(
+                "sdf_repeat({p}, {}, vec3<f32>({:?}, {:?}, {:?}))",
+                vec3_wgsl(period),
+                count.x as f32,
+                count.y as f32,
+                count.z as f32
+            )),
+        }
+    }
+}
+
+#[allow(unused)]
+impl SdfShape {
+    pub fn new(sdf: Sdf, material: Material) -> Self {
+        // padded so that the surface never lies on the box where the sphere
+        // tracing starts
+        let bbox = sdf.bbox().pad(0.001);
+        Self {
+            prim: SdfPrim { sdf, bbox },
+            material,
+        }
+    }
+}
+
+impl Object for SdfShape {
+    fn as_prims(&self) -> Vec<Box<dyn ObjPrim>> {
+        vec![Box::new(self.prim.clone())]
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+impl ObjPrim for SdfPrim {
+    fn as_shader_type(&self, mat_idx: u32) -> shader_type::Object {
+        shader_type::Object {
+            obj_type: shader_type::OBJ_SDF,
+            mat_idx,
+            data_idx: 0,
+            motion_idx: -1,
+            v: [self.bbox.min, self.bbox.max, cgmath::vec3(0.0, 0.0, 0.0)],
+            n: [
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+                cgmath::vec3(0.0, 0.0, 0.0),
+            ],
+        }
+    }
+
+    fn bbox(&self) -> &AABB {
+        &self.bbox
+    }
+
+    fn sdf(&self) -> Option<&Sdf> {
+        Some(&self.sdf)
+    }
+}
+
+// WGSL source of the `sdf_eval` function dispatching to the distance function
+// of each SDF object by its index
+pub fn as_wgsl(sdfs: &[&Sdf]) -> String {
+    let cases = sdfs
+        .iter()
+        .enumerate()
+        .map(|(i, sdf)| {
+            format!(
+                "        case {i}u: {{\n            return {};\n        }}\n",
+                sdf.as_wgsl("p")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "fn sdf_eval(idx: u32, p: vec3<f32>) -> f32 {{\n    switch idx {{\n{cases}\n        default: {{\n            return SDF_MAX_DISTANCE;\n        }}\n    }}\n}}\n"
+    )
+}
+
+fn vec3_wgsl(v: &cgmath::Vector3<f32>) -> String {
+    format!("vec3<f32>({:?}, {:?}, {:?})", v.x, v.y, v.z)
+}
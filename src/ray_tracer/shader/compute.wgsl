@@ -1,5 +1,16 @@
 /* constant-------------------------------------------------------------------*/
-const BVH_MAX_STACK: u32 = 16;
+// deep enough for the LBVH, whose depth is bounded by the bits of its keys
+const BVH_MAX_STACK: u32 = 64;
+// samples taken before the error of a pixel is trusted
+const ADAPTIVE_MIN_SAMPLE: f32 = 16.0;
+// samples the history of a reprojected pixel is clamped to, so that stale
+// shading fades out as the new view accumulates
+const REPROJECT_MAX_SAMPLE: f32 = 64.0;
+// distance between the first hits of the history and the new view, relative
+// to the depth, beyond which the history is seen as disoccluded
+const REPROJECT_TOLERANCE: f32 = 0.02;
+// distance of the background along escaping rays
+const REPROJECT_FAR: f32 = 1e5;
 
 override WORKGROUP_SIZE_X: u32 = 16;
 override WORKGROUP_SIZE_Y: u32 = 16;
@@ -9,32 +20,185 @@ override WORKGROUP_SIZE_Y: u32 = 16;
 @group(0) @binding(1) var<uniform> param: Param;
 
 /* buffer---------------------------------------------------------------------*/
-@group(1) @binding(0) var<storage, read_write> frame: array<vec3<f32>>;
+@group(1) @binding(0) var<storage, read_write> frame: array<Pixel>;
 @group(1) @binding(1) var<storage, read> bvh: BVH;
 @group(1) @binding(2) var<storage, read> objects: Objects;
 @group(1) @binding(3) var<storage, read> materials: Materials;
+@group(1) @binding(4) var<storage, read> motions: Motions;
+@group(1) @binding(5) var<storage, read_write> convergence: Convergence;
+
+/* texture--------------------------------------------------------------------*/
+@group(2) @binding(0) var aperture_mask: texture_2d<f32>;
+@group(2) @binding(1) var blue_noise: texture_2d<f32>;
+
+/* reprojection---------------------------------------------------------------*/
+@group(3) @binding(0) var<uniform> previous_camera: CameraParam;
+@group(3) @binding(1) var<storage, read> history: array<Pixel>;
+
+/* private--------------------------------------------------------------------*/
+// rays traced by the invocation
+var<private> num_ray: u32;
 
 /* function-------------------------------------------------------------------*/
 @compute
 @workgroup_size(WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, 1)
-fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
-    if any(id.xy > param.window_size) {
+fn cs_main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id.xy >= param.dispatch_size) {
         return;
     }
-
-    rng_init(id.xy, param.window_size, stat.frame_counter);
+    let id = global_id.xy + param.dispatch_offset;
 
     let pixel = &frame[id.x + id.y * param.window_size.x];
-    if (stat.frame_counter == 0) {
-        *pixel = vec3<f32>(0.0, 0.0, 0.0);
+    if stat.clear != 0u {
+        *pixel = pixel_new();
+    }
+
+    let error = pixel_error(*pixel);
+    sum_error(u32(min(error, 1.0) * 256.0));
+    if param.noise_threshold > 0.0 && (*pixel).num_sample >= ADAPTIVE_MIN_SAMPLE
+        && error < param.noise_threshold {
+        return;
     }
+    atomicAdd(&convergence.num_active, 1u);
 
-    let tex_coord = vec2<f32>((f32(id.x) + rng_f32()) / f32(param.window_size.x),
-        (f32(id.y) + rng_f32()) / f32(param.window_size.y));
-    let ray = camera_get_ray(param.camera, tex_coord);
-    let color = color(ray, param.max_bounce);
+    // accumulated locally to touch the frame buffer once per dispatch
+    var accum = *pixel;
+    for (var i = 0u; i < stat.num_sample; i++) {
+        rng_init(id + param.pixel_offset, param.image_size, stat.sample_index + i,
+            param.sampler_type);
 
-    *pixel += color;
+        var ray: Ray;
+        var weight: f32;
+        var sample = vec3<f32>(0.0, 0.0, 0.0);
+        if pixel_sample_ray(id, &ray, &weight) {
+            sample = color(ray, param.max_bounce);
+        }
+
+        let lum = luminance(sample);
+        accum.color += weight * sample;
+        accum.weight += weight;
+        accum.num_sample += 1.0;
+        accum.lum += lum;
+        accum.moment += lum * lum;
+    }
+    *pixel = accum;
+
+    count_rays(num_ray);
+}
+
+fn count_rays(n: u32) {
+    // carry into the high word when the low word wraps around
+    let low = atomicAdd(&convergence.num_ray_low, n);
+    if low + n < low {
+        atomicAdd(&convergence.num_ray_high, 1u);
+    }
+}
+
+fn sum_error(n: u32) {
+    let low = atomicAdd(&convergence.error_sum_low, n);
+    if low + n < low {
+        atomicAdd(&convergence.error_sum_high, 1u);
+    }
+}
+
+// warps the accumulation of the previous view into the current one, pixels
+// whose first hit was not seen by the previous view, or whose shading depends
+// on the view, start over
+@compute
+@workgroup_size(WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, 1)
+fn cs_reproject(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= param.window_size) {
+        return;
+    }
+
+    var pixel = pixel_new();
+    var position: vec3<f32>;
+    let depth = first_hit(param.camera, pixel_center(id.xy), &position);
+
+    let previous = camera_project(previous_camera, position);
+    if depth != 0.0 && previous.z > 0.0 && all(previous.xy >= vec2<f32>(0.0))
+        && all(previous.xy < vec2<f32>(1.0)) {
+        let texel = vec2<u32>(previous.xy * vec2<f32>(param.window_size));
+        let old = history[texel.x + texel.y * param.window_size.x];
+
+        // the first hit of the history is traced again from the previous
+        // view, as the scene has not changed since
+        var old_position: vec3<f32>;
+        let old_depth = first_hit(previous_camera, pixel_center(texel), &old_position);
+
+        let same_surface = (depth > 0.0 && old_depth > 0.0
+            && distance(old_position, position) < REPROJECT_TOLERANCE * depth)
+            || (depth < 0.0 && old_depth < 0.0);
+        if same_surface && old.num_sample > 0.0 {
+            let keep = min(REPROJECT_MAX_SAMPLE / old.num_sample, 1.0);
+            pixel.color = keep * old.color;
+            pixel.weight = keep * old.weight;
+            pixel.num_sample = keep * old.num_sample;
+            pixel.lum = keep * old.lum;
+            pixel.moment = keep * old.moment;
+        }
+    }
+
+    frame[id.x + id.y * param.window_size.x] = pixel;
+}
+
+// the sample is spread uniformly over the support of the reconstruction filter
+// and weighted by it, each pixel gathering its own samples
+fn pixel_sample_ray(id: vec2<u32>, ray: ptr<function, Ray>, weight: ptr<function, f32>) -> bool {
+    let offset = param.filter_radius * (2.0 * vec2<f32>(rng_f32(), rng_f32()) - 1.0);
+    *weight = filter_weight(param.filter_type, param.filter_radius, offset);
+    let tex_coord = (vec2<f32>(id) + 0.5 + offset) / vec2<f32>(param.window_size);
+    return camera_get_ray(param.camera, tex_coord, ray);
+}
+
+// empty accumulation
+fn pixel_new() -> Pixel {
+    return Pixel(vec3<f32>(0.0, 0.0, 0.0), 0.0, 0.0, 0.0, 0.0);
+}
+
+fn pixel_center(id: vec2<u32>) -> vec2<f32> {
+    return (vec2<f32>(id) + 0.5) / vec2<f32>(param.window_size);
+}
+
+// first hit of the ray through frame_coord without depth of field or motion
+// blur, returns the distance to it, negative if the ray escapes to the
+// background, and 0 if there is no surface to reproject, outside of the image
+// or on a reflective or transparent surface whose shading moves with the view
+fn first_hit(camera: CameraParam, frame_coord: vec2<f32>, position: ptr<function, vec3<f32>>) -> f32 {
+    var pinhole = camera;
+    pinhole.lens_radius = 0.0;
+    pinhole.shutter = vec2<f32>(pinhole.shutter.x);
+
+    var ray: Ray;
+    if !camera_get_ray(pinhole, frame_coord, &ray) {
+        *position = vec3<f32>(0.0, 0.0, 0.0);
+        return 0.0;
+    }
+
+    var hit: HitRecord;
+    if calculate_hit(ray, Interval(0.001, 1000.0), &hit) {
+        *position = ray_at(ray, hit.t);
+        let material = materials.materials[objects.objects[hit.object_idx].mat_idx];
+        if material.mat_type != MAT_DIFFUSE {
+            return 0.0;
+        }
+        return hit.t;
+    }
+    *position = ray_at(ray, REPROJECT_FAR);
+    return -1.0;
+}
+
+// relative standard error of the mean luminance, with a floor on the mean so
+// that dark pixels can converge
+fn pixel_error(pixel: Pixel) -> f32 {
+    let n = pixel.num_sample;
+    if n < 2.0 {
+        return 1.0;
+    }
+
+    let mean = pixel.lum / n;
+    let variance = max(pixel.moment / n - mean * mean, 0.0) / (n - 1.0);
+    return sqrt(variance) / max(mean, 0.01);
 }
 
 fn color(_ray: Ray, max_bounce: u32) -> vec3<f32> {
@@ -45,6 +209,7 @@ fn color(_ray: Ray, max_bounce: u32) -> vec3<f32> {
 
     for (var i: u32 = 0; i < max_bounce; i += 1) {
         var hit: HitRecord;
+        num_ray += 1u;
 
         if (calculate_hit(ray, Interval(0.001, 1000.0), &hit)) {
             let object = objects.objects[hit.object_idx];
@@ -54,9 +219,7 @@ fn color(_ray: Ray, max_bounce: u32) -> vec3<f32> {
             ray = material_scatter(material, ray, hit);
         } else {
             // ray missed, output background color
-            let t = 0.5 * (ray.direction.y + 1.0);
-            color = (1.0 - t) * vec3<f32>(1.0, 1.0, 1.0) + t * vec3<f32>(0.5, 0.7, 1.0);
-        
+            color = background(ray.direction);
             break;
         }
     }
@@ -64,6 +227,12 @@ fn color(_ray: Ray, max_bounce: u32) -> vec3<f32> {
     return albedo * color;
 }
 
+// sky gradient lighting the scene
+fn background(direction: vec3<f32>) -> vec3<f32> {
+    let t = 0.5 * (direction.y + 1.0);
+    return (1.0 - t) * vec3<f32>(1.0, 1.0, 1.0) + t * vec3<f32>(0.5, 0.7, 1.0);
+}
+
 fn calculate_hit(ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
     switch param.hit_algorithm {
         case HIT_BRUTE: {
@@ -74,6 +243,10 @@ fn calculate_hit(ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) ->
             return bvh_hit(ray, interval, hit);
         }
 
+        case HIT_RAY_QUERY: {
+            return ray_query_hit(ray, interval, hit);
+        }
+
         default: {
             return false;
         }
@@ -108,11 +281,17 @@ fn bvh_hit(ray: Ray, _interval: Interval, _hit: ptr<function, HitRecord>) -> boo
         let node = bvh.nodes[stack[stack_top]];
         stack_top--;
 
-        if (!bbox_hit(node.bbox, ray, interval)) {
+        if (!bbox_hit(bvh_node_bbox(node, ray.time), ray, interval)) {
             continue;
         }
 
         if (node.object_idx >= 0) {
+            // left to the hardware acceleration structure
+            if param.hit_algorithm == HIT_RAY_QUERY
+                && ray_query_contains(objects.objects[node.object_idx]) {
+                continue;
+            }
+
             if (object_hit(objects.objects[node.object_idx], ray, interval, _hit)) {
                 (*_hit).object_idx = node.object_idx;
 
@@ -1,3 +1,11 @@
+/* constant-------------------------------------------------------------------*/
+const APERTURE_MAX_SAMPLE: u32 = 32;
+
+const CSG_MAX_STEP: u32 = 64;
+const CSG_EPSILON: f32 = 0.0001;
+const CSG_MAX_DISTANCE: f32 = 1e9;
+
+/* type-----------------------------------------------------------------------*/
 struct Interval {
     min: f32,
     max: f32,
@@ -6,6 +14,7 @@ struct Interval {
 struct Ray {
     origin: vec3<f32>,
     direction: vec3<f32>,
+    time: f32,
 }
 
 struct HitRecord {
@@ -15,6 +24,7 @@ struct HitRecord {
     object_idx: i32,
 }
 
+/* function-------------------------------------------------------------------*/
 fn interval_contains(interval: Interval, value: f32) -> bool {
     return value >= interval.min && value <= interval.max;
 }
@@ -23,19 +33,207 @@ fn interval_overlaps(interval1: Interval, interval2: Interval) -> bool {
     return interval1.min <= interval2.max && interval2.min <= interval1.max;
 }
 
-fn ray_new(origin: vec3<f32>, direction: vec3<f32>) -> Ray {
-    return Ray(origin, normalize(direction));
+fn ray_new(origin: vec3<f32>, direction: vec3<f32>, time: f32) -> Ray {
+    return Ray(origin, normalize(direction), time);
 }
 
 fn ray_at(ray: Ray, t: f32) -> vec3<f32> {
     return ray.origin + t * ray.direction;
 }
 
-fn camera_get_ray(camera: CameraParam, tex_coord: vec2<f32>) -> Ray {
-    let rd = camera.lens_radius * rng_unit_disk_f32();
-    let ray_origin = camera.position + rd.x * camera.horizontal + rd.y * camera.vertical;
-    let look_at = camera.start + tex_coord.x * camera.vx + tex_coord.y * camera.vy;
-    return ray_new(ray_origin, look_at - ray_origin);
+// generates the primary ray through frame_coord of the frame buffer, returns
+// false if the point is outside of the image circle of the projection
+fn camera_get_ray(camera: CameraParam, frame_coord: vec2<f32>, ray: ptr<function, Ray>) -> bool {
+    let tex_coord = camera.window_min + frame_coord * camera.window_size;
+    let time = mix(camera.shutter.x, camera.shutter.y, rng_f32());
+
+    switch camera.projection {
+        case PROJ_PERSPECTIVE, default: {
+            let rd = camera.lens_radius * camera_aperture_sample(camera, tex_coord);
+            let ray_origin = camera.position + rd.x * camera.horizontal + rd.y * camera.vertical;
+            let look_at = camera.start + tex_coord.x * camera.vx + tex_coord.y * camera.vy;
+            *ray = ray_new(ray_origin, look_at - ray_origin, time);
+        }
+        case PROJ_ORTHOGRAPHIC: {
+            // parallel rays through the focus plane, with the lens placed at
+            // focus distance behind each point
+            let rd = camera.lens_radius * camera_aperture_sample(camera, tex_coord);
+            let look_at = camera.start + tex_coord.x * camera.vx + tex_coord.y * camera.vy;
+            let ray_origin = look_at - camera.focus_distance * camera.forward
+                + rd.x * camera.horizontal + rd.y * camera.vertical;
+            *ray = ray_new(ray_origin, look_at - ray_origin, time);
+        }
+        case PROJ_FISHEYE: {
+            // equidistant fisheye, the field of view spans the image height
+            let xy = vec2<f32>((2.0 * tex_coord.x - 1.0) * camera.aspect_ratio,
+                1.0 - 2.0 * tex_coord.y);
+            let theta = length(xy) * camera.fov / 2.0;
+            if theta > PI {
+                return false;
+            }
+            let phi = atan2(xy.y, xy.x);
+            let direction = sin(theta) * (cos(phi) * camera.horizontal + sin(phi) * camera.vertical)
+                + cos(theta) * camera.forward;
+            *ray = ray_new(camera.position, direction, time);
+        }
+        case PROJ_EQUIRECTANGULAR: {
+            // full 360 by 180 degree panorama
+            let longitude = (tex_coord.x - 0.5) * 2.0 * PI;
+            let latitude = (0.5 - tex_coord.y) * PI;
+            let direction = cos(latitude) * (sin(longitude) * camera.horizontal
+                + cos(longitude) * camera.forward) + sin(latitude) * camera.vertical;
+            *ray = ray_new(camera.position, direction, time);
+        }
+        case PROJ_CYLINDRICAL: {
+            // 360 degree around the vertical axis, perspective along it
+            let longitude = (tex_coord.x - 0.5) * 2.0 * PI;
+            let height = (1.0 - 2.0 * tex_coord.y) * tan(camera.fov / 2.0);
+            let direction = sin(longitude) * camera.horizontal + cos(longitude) * camera.forward
+                + height * camera.vertical;
+            *ray = ray_new(camera.position, direction, time);
+        }
+    }
+
+    return true;
+}
+
+// inverse of camera_get_ray through the lens center, the frame coordinates of
+// a point and whether it is in front of the camera
+fn camera_project(camera: CameraParam, p: vec3<f32>) -> vec3<f32> {
+    let d = p - camera.position;
+    let x = dot(d, camera.horizontal);
+    let y = dot(d, camera.vertical);
+    let z = dot(d, camera.forward);
+
+    var tex_coord: vec2<f32>;
+    var valid = true;
+    switch camera.projection {
+        case PROJ_PERSPECTIVE, default: {
+            // through the focus plane along the ray from the lens center
+            let normal = cross(camera.vx, camera.vy);
+            let look_at = camera.position + d * dot(camera.start - camera.position, normal)
+                / dot(d, normal);
+            tex_coord = focus_plane_coord(camera, look_at);
+            valid = z > 0.0;
+        }
+        case PROJ_ORTHOGRAPHIC: {
+            tex_coord = focus_plane_coord(camera, p);
+        }
+        case PROJ_FISHEYE: {
+            let theta = acos(clamp(z / length(d), -1.0, 1.0));
+            let phi = atan2(y, x);
+            let r = 2.0 * theta / camera.fov;
+            tex_coord = vec2<f32>(0.5 + 0.5 * r * cos(phi) / camera.aspect_ratio,
+                0.5 - 0.5 * r * sin(phi));
+        }
+        case PROJ_EQUIRECTANGULAR: {
+            let longitude = atan2(x, z);
+            let latitude = asin(clamp(y / length(d), -1.0, 1.0));
+            tex_coord = vec2<f32>(longitude / (2.0 * PI) + 0.5, 0.5 - latitude / PI);
+        }
+        case PROJ_CYLINDRICAL: {
+            let longitude = atan2(x, z);
+            let height = y / length(vec2<f32>(x, z));
+            tex_coord = vec2<f32>(longitude / (2.0 * PI) + 0.5,
+                0.5 - 0.5 * height / tan(camera.fov / 2.0));
+        }
+    }
+
+    let frame_coord = (tex_coord - camera.window_min) / camera.window_size;
+    return vec3<f32>(frame_coord, select(0.0, 1.0, valid));
+}
+
+// texture coordinates of a point projected onto the focus plane along the
+// viewing direction
+fn focus_plane_coord(camera: CameraParam, p: vec3<f32>) -> vec2<f32> {
+    let d = p - camera.start;
+    return vec2<f32>(dot(d, camera.vx) / dot(camera.vx, camera.vx),
+        dot(d, camera.vy) / dot(camera.vy, camera.vy));
+}
+
+// point on the unit aperture, with cat-eye vignetting modeled as the aperture
+// being clipped by a unit disk shifted towards the edge of the image
+fn camera_aperture_sample(camera: CameraParam, tex_coord: vec2<f32>) -> vec2<f32> {
+    let shift = camera.cat_eye * vec2<f32>(2.0 * tex_coord.x - 1.0, 1.0 - 2.0 * tex_coord.y);
+
+    for (var i = 0u; i < APERTURE_MAX_SAMPLE; i++) {
+        var p: vec2<f32>;
+        switch camera.aperture_shape {
+            case APERTURE_POLYGON: {
+                p = rng_unit_polygon_f32(camera.aperture_blades, camera.aperture_rotation);
+            }
+
+            case APERTURE_MASK: {
+                p = 2.0 * vec2<f32>(rng_f32(), rng_f32()) - 1.0;
+                let size = vec2<f32>(textureDimensions(aperture_mask));
+                let texel = vec2<u32>(min((vec2<f32>(p.x, -p.y) * 0.5 + 0.5) * size, size - 1.0));
+                if rng_f32() >= textureLoad(aperture_mask, texel, 0).r {
+                    continue;
+                }
+            }
+
+            default: {
+                p = rng_unit_disk_f32();
+            }
+        }
+
+        if length(p + shift) <= 1.0 {
+            return p;
+        }
+    }
+
+    return vec2<f32>(0.0);
+}
+
+// separable reconstruction filter at an offset from the pixel center, all but
+// the box filter are stretched to vanish at the radius
+fn filter_weight(filter_type: u32, radius: f32, offset: vec2<f32>) -> f32 {
+    return filter_1d(filter_type, radius, offset.x) * filter_1d(filter_type, radius, offset.y);
+}
+
+fn filter_1d(filter_type: u32, radius: f32, x: f32) -> f32 {
+    let x_abs = abs(x);
+    switch filter_type {
+        case FILTER_GAUSSIAN: {
+            // truncated at 3 sigma and shifted to reach 0 there
+            let sigma = radius / 3.0;
+            let gaussian = exp(-x * x / (2.0 * sigma * sigma));
+            return max(gaussian - exp(-4.5), 0.0);
+        }
+
+        case FILTER_MITCHELL: {
+            // Mitchell-Netravali with B = C = 1/3 over [-2, 2]
+            let t = 2.0 * x_abs / radius;
+            if t >= 2.0 {
+                return 0.0;
+            } else if t >= 1.0 {
+                return (-7.0 / 3.0 * t * t * t + 12.0 * t * t - 20.0 * t + 32.0 / 3.0) / 6.0;
+            } else {
+                return (7.0 * t * t * t - 12.0 * t * t + 16.0 / 3.0) / 6.0;
+            }
+        }
+
+        case FILTER_BLACKMAN_HARRIS: {
+            if x_abs >= radius {
+                return 0.0;
+            }
+            let t = 2.0 * PI * (0.5 + 0.5 * x / radius);
+            return 0.35875 - 0.48829 * cos(t) + 0.14128 * cos(2.0 * t) - 0.01168 * cos(3.0 * t);
+        }
+
+        case FILTER_LANCZOS: {
+            // sinc windowed by a sinc stretched to the radius, with one lobe
+            // per pixel of the radius
+            if x_abs >= radius {
+                return 0.0;
+            }
+            return sinc(x) * sinc(x / radius);
+        }
+
+        default: {
+            return 1.0;
+        }
+    }
 }
 
 fn bbox_hit(bbox: AABB, ray: Ray, interval: Interval) -> bool {
@@ -48,7 +246,74 @@ fn bbox_hit(bbox: AABB, ray: Ray, interval: Interval) -> bool {
     return tmin_max < tmax_min && interval_overlaps(interval, Interval(tmin_max, tmax_min));
 }
 
+// bounding box of the node at the time of the ray, interpolated between the
+// boxes at the start and the end of the shutter interval
+fn bvh_node_bbox(node: BVHNode, time: f32) -> AABB {
+    let s = clamp(time, 0.0, 1.0);
+    return AABB(mix(node.bbox.min, node.bbox_end.min, s), mix(node.bbox.max, node.bbox_end.max, s));
+}
+
+fn motion_transform(motion_idx: i32, time: f32) -> mat4x4<f32> {
+    let motion = &motions.motions[motion_idx];
+
+    if time <= (*motion).times[0] {
+        return (*motion).transforms[0];
+    }
+
+    for (var i = 1u; i < (*motion).num_keyframe; i++) {
+        if time <= (*motion).times[i] {
+            let t0 = (*motion).times[i - 1];
+            let s = (time - t0) / max((*motion).times[i] - t0, 0.00001);
+            return (*motion).transforms[i - 1] * (1.0 - s) + (*motion).transforms[i] * s;
+        }
+    }
+
+    return (*motion).transforms[(*motion).num_keyframe - 1];
+}
+
+fn mat3_inverse(m: mat3x3<f32>) -> mat3x3<f32> {
+    let c0 = cross(m[1], m[2]);
+    let c1 = cross(m[2], m[0]);
+    let c2 = cross(m[0], m[1]);
+    return transpose(mat3x3<f32>(c0, c1, c2)) * (1.0 / dot(m[0], c0));
+}
+
 fn object_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    if object.motion_idx < 0 {
+        return static_object_hit(object, ray, interval, hit);
+    }
+
+    // moving objects are hit in their own space at the time of the ray, the
+    // local ray is normalized so the distances are scaled accordingly
+    let transform = motion_transform(object.motion_idx, ray.time);
+    let inverse = mat3_inverse(mat3x3<f32>(transform[0].xyz, transform[1].xyz, transform[2].xyz));
+    let direction = inverse * ray.direction;
+    let scale = length(direction);
+
+    let local_ray = Ray(inverse * (ray.origin - transform[3].xyz), direction / scale, ray.time);
+    let local_interval = Interval(interval.min * scale, interval.max * scale);
+    if !static_object_hit(object, local_ray, local_interval, hit) {
+        return false;
+    }
+
+    (*hit).t /= scale;
+    (*hit).point = ray_at(ray, (*hit).t);
+    (*hit).normal = normalize(transpose(inverse) * (*hit).normal);
+
+    return true;
+}
+
+fn static_object_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    if object.obj_type == OBJ_CSG {
+        return csg_hit(object, ray, interval, hit);
+    }
+
+    return primitive_hit(object, ray, interval, hit);
+}
+
+// hit of non-CSG objects, split from object_hit since CSG operands are hit
+// through here and WGSL does not allow recursion
+fn primitive_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
     switch object.obj_type {
         case OBJ_TRIANGLE: {
             return triangle_hit(object, ray, interval, hit);
@@ -58,6 +323,30 @@ fn object_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, H
             return sphere_hit(object, ray, interval, hit);
         }
 
+        case OBJ_QUAD: {
+            return quad_hit(object, ray, interval, hit);
+        }
+
+        case OBJ_BOX: {
+            return box_hit(object, ray, interval, hit);
+        }
+
+        case OBJ_DISK: {
+            return disk_hit(object, ray, interval, hit);
+        }
+
+        case OBJ_CYLINDER: {
+            return cylinder_hit(object, ray, interval, hit);
+        }
+
+        case OBJ_CONE: {
+            return cone_hit(object, ray, interval, hit);
+        }
+
+        case OBJ_SDF: {
+            return sdf_hit(object, ray, interval, hit);
+        }
+
         default: {
             return false;
         }
@@ -96,26 +385,29 @@ fn triangle_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function,
 
     (*hit).point = ray_at(ray, t);
     (*hit).t = t;
+    (*hit).normal = triangle_normal(object, vec2<f32>(u, v));
 
+    return true;
+}
 
+// at the barycentric coordinates of the second and the third vertices
+fn triangle_normal(object: Object, uv: vec2<f32>) -> vec3<f32> {
     switch param.shading_algorithm {
         case SHADE_FLAT: {
-            (*hit).normal = normalize(cross(e1, e2));
+            return normalize(cross(object.v[1] - object.v[0], object.v[2] - object.v[0]));
         }
 
         case SHADE_SMOOTH: {
-            let n1 = object.n[0] * (1.0 - u - v);
-            let n2 = object.n[1] * u;
-            let n3 = object.n[2] * v;
-            (*hit).normal = normalize(n1 + n2 + n3);
+            let n1 = object.n[0] * (1.0 - uv.x - uv.y);
+            let n2 = object.n[1] * uv.x;
+            let n3 = object.n[2] * uv.y;
+            return normalize(n1 + n2 + n3);
         }
 
         default: {
-            (*hit).normal = vec3<f32>(0.0);
+            return vec3<f32>(0.0);
         }
     }
-
-    return true;
 }
 
 fn sphere_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
@@ -147,6 +439,325 @@ fn sphere_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, H
     return true;
 }
 
+// https://raytracing.github.io/books/RayTracingTheNextWeek.html#quadrilaterals
+fn quad_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    let q = object.v[0];
+    let u = object.v[1];
+    let v = object.v[2];
+    let n = cross(u, v);
+
+    let denom = dot(n, ray.direction);
+    if abs(denom) < 0.00001 {
+        return false;
+    }
+
+    let t = dot(n, q - ray.origin) / denom;
+    if !interval_contains(interval, t) {
+        return false;
+    }
+
+    // planar coordinates of the hit point in the basis of the edges
+    let point = ray_at(ray, t);
+    let w = n / dot(n, n);
+    let alpha = dot(w, cross(point - q, v));
+    let beta = dot(w, cross(u, point - q));
+    if alpha < 0.0 || alpha > 1.0 || beta < 0.0 || beta > 1.0 {
+        return false;
+    }
+
+    (*hit).point = point;
+    (*hit).t = t;
+    (*hit).normal = object.n[0];
+
+    return true;
+}
+
+fn box_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    let center = object.v[0];
+    let axes = mat3x3<f32>(object.v[1], object.v[2], object.n[0]);
+    let inv_len2 = 1.0 / vec3<f32>(dot(axes[0], axes[0]), dot(axes[1], axes[1]),
+        dot(axes[2], axes[2]));
+
+    // ray in the local space of the box, where the box is [-1, 1]^3, the ray
+    // parameter t is preserved by the affine mapping
+    let origin = (transpose(axes) * (ray.origin - center)) * inv_len2;
+    let direction = (transpose(axes) * ray.direction) * inv_len2;
+
+    let t0 = (vec3<f32>(-1.0) - origin) / direction;
+    let t1 = (vec3<f32>(1.0) - origin) / direction;
+    let tmin = min(t0, t1);
+    let tmax = max(t0, t1);
+    let t_near = max(tmin.x, max(tmin.y, tmin.z));
+    let t_far = min(tmax.x, min(tmax.y, tmax.z));
+    if t_near > t_far {
+        return false;
+    }
+
+    var t = t_near;
+    if !interval_contains(interval, t) {
+        t = t_far;
+        if !interval_contains(interval, t) {
+            return false;
+        }
+    }
+
+    // the face hit is the one on the axis where the local point is furthest
+    // from the center
+    let local = abs(origin + t * direction);
+    var axis = 2;
+    if local.x >= local.y && local.x >= local.z {
+        axis = 0;
+    } else if local.y >= local.z {
+        axis = 1;
+    }
+
+    (*hit).point = ray_at(ray, t);
+    (*hit).t = t;
+    (*hit).normal = normalize(axes[axis]) * sign((origin + t * direction)[axis]);
+
+    return true;
+}
+
+fn disk_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    let center = object.v[0];
+    let radius = object.v[1].x;
+    let normal = object.n[0];
+
+    let denom = dot(normal, ray.direction);
+    if abs(denom) < 0.00001 {
+        return false;
+    }
+
+    let t = dot(normal, center - ray.origin) / denom;
+    if !interval_contains(interval, t) {
+        return false;
+    }
+
+    let point = ray_at(ray, t);
+    let offset = point - center;
+    if dot(offset, offset) > radius * radius {
+        return false;
+    }
+
+    (*hit).point = point;
+    (*hit).t = t;
+    (*hit).normal = normal;
+
+    return true;
+}
+
+// hit of the ray with the circular cap of a cylinder or cone
+fn cap_hit(center: vec3<f32>, normal: vec3<f32>, radius: f32, ray: Ray,
+    interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    let denom = dot(normal, ray.direction);
+    if abs(denom) < 0.00001 {
+        return false;
+    }
+
+    let t = dot(normal, center - ray.origin) / denom;
+    if !interval_contains(interval, t) {
+        return false;
+    }
+
+    let offset = ray_at(ray, t) - center;
+    if dot(offset, offset) > radius * radius {
+        return false;
+    }
+
+    (*hit).point = ray_at(ray, t);
+    (*hit).t = t;
+    (*hit).normal = normal;
+
+    return true;
+}
+
+// https://iquilezles.org/articles/intersectors
+fn cylinder_hit(object: Object, ray: Ray, _interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    let base = object.v[0];
+    let top = object.v[1];
+    let radius = object.v[2].x;
+
+    var interval = _interval;
+    var is_hit = false;
+
+    let ba = top - base;
+    let oc = ray.origin - base;
+    let baba = dot(ba, ba);
+    let bard = dot(ba, ray.direction);
+    let baoc = dot(ba, oc);
+
+    // lateral surface, both roots are checked so that hits from the inside are
+    // also found
+    let k2 = baba - bard * bard;
+    let k1 = baba * dot(oc, ray.direction) - baoc * bard;
+    let k0 = baba * dot(oc, oc) - baoc * baoc - radius * radius * baba;
+    let disc = k1 * k1 - k2 * k0;
+    if disc >= 0.0 && abs(k2) > 0.00001 {
+        let disc_sqrt = sqrt(disc);
+        var roots = array<f32, 2>((-k1 - disc_sqrt) / k2, (-k1 + disc_sqrt) / k2);
+        for (var i = 0; i < 2; i++) {
+            let t = roots[i];
+            let y = baoc + t * bard;
+            if y > 0.0 && y < baba && interval_contains(interval, t) {
+                (*hit).point = ray_at(ray, t);
+                (*hit).t = t;
+                (*hit).normal = (oc + t * ray.direction - ba * y / baba) / radius;
+
+                interval.max = t;
+                is_hit = true;
+            }
+        }
+    }
+
+    let axis = ba / sqrt(baba);
+    if cap_hit(base, -axis, radius, ray, interval, hit) {
+        interval.max = (*hit).t;
+        is_hit = true;
+    }
+    if cap_hit(top, axis, radius, ray, interval, hit) {
+        is_hit = true;
+    }
+
+    return is_hit;
+}
+
+fn cone_hit(object: Object, ray: Ray, _interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    let base = object.v[0];
+    let apex = object.v[1];
+    let radius = object.v[2].x;
+
+    var interval = _interval;
+    var is_hit = false;
+
+    let height = length(base - apex);
+    let axis = (base - apex) / height;
+    let cos2 = height * height / (height * height + radius * radius);
+
+    // points p on the infinite double cone satisfy
+    // dot(p - apex, axis)^2 = |p - apex|^2 cos^2
+    let co = ray.origin - apex;
+    let dv = dot(ray.direction, axis);
+    let cov = dot(co, axis);
+    let a = dv * dv - cos2;
+    let b = 2.0 * (dv * cov - dot(ray.direction, co) * cos2);
+    let c = cov * cov - dot(co, co) * cos2;
+    let disc = b * b - 4.0 * a * c;
+    if disc >= 0.0 && abs(a) > 0.00001 {
+        let disc_sqrt = sqrt(disc);
+        let t0 = (-b - disc_sqrt) / (2.0 * a);
+        let t1 = (-b + disc_sqrt) / (2.0 * a);
+        var roots = array<f32, 2>(min(t0, t1), max(t0, t1));
+        for (var i = 0; i < 2; i++) {
+            let t = roots[i];
+            let cp = ray_at(ray, t) - apex;
+            let y = dot(cp, axis);
+            if y > 0.0 && y < height && interval_contains(interval, t) {
+                (*hit).point = ray_at(ray, t);
+                (*hit).t = t;
+                (*hit).normal = normalize(cos2 * cp - y * axis);
+
+                interval.max = t;
+                is_hit = true;
+                break;
+            }
+        }
+    }
+
+    if cap_hit(base, axis, radius, ray, interval, hit) {
+        is_hit = true;
+    }
+
+    return is_hit;
+}
+
+// closest hit of the ray with the objects in [start, end) of the object list
+fn objects_hit(start: u32, end: u32, ray: Ray, _interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    var interval = _interval;
+    var is_hit = false;
+
+    for (var i = start; i < end; i++) {
+        if (primitive_hit(objects.objects[i], ray, interval, hit)) {
+            interval.max = (*hit).t;
+            is_hit = true;
+        }
+    }
+
+    return is_hit;
+}
+
+fn csg_inside(op: u32, inside_a: bool, inside_b: bool) -> bool {
+    switch op {
+        case CSG_UNION: {
+            return inside_a || inside_b;
+        }
+
+        case CSG_INTERSECTION: {
+            return inside_a && inside_b;
+        }
+
+        case CSG_DIFFERENCE: {
+            return inside_a && !inside_b;
+        }
+
+        default: {
+            return false;
+        }
+    }
+}
+
+// walks the surface crossings of both operands along the ray in order, keeping
+// track of whether the ray is inside each of them, until the inside state of
+// the combined solid changes
+fn csg_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    let a = vec2<u32>(u32(object.v[0].x), u32(object.v[0].y));
+    let b = vec2<u32>(u32(object.v[0].y), u32(object.v[0].z));
+
+    var hit_a: HitRecord;
+    var hit_b: HitRecord;
+    var has_a = objects_hit(a.x, a.y, ray, Interval(interval.min, CSG_MAX_DISTANCE), &hit_a);
+    var has_b = objects_hit(b.x, b.y, ray, Interval(interval.min, CSG_MAX_DISTANCE), &hit_b);
+
+    // the ray starts inside an operand if its first crossing leaves it
+    var inside_a = has_a && dot(ray.direction, hit_a.normal) > 0.0;
+    var inside_b = has_b && dot(ray.direction, hit_b.normal) > 0.0;
+    var inside = csg_inside(object.data_idx, inside_a, inside_b);
+
+    for (var i = 0u; i < CSG_MAX_STEP && (has_a || has_b); i++) {
+        let is_a = has_a && (!has_b || hit_a.t <= hit_b.t);
+
+        var crossing: HitRecord;
+        if is_a {
+            crossing = hit_a;
+            inside_a = dot(ray.direction, hit_a.normal) < 0.0;
+        } else {
+            crossing = hit_b;
+            inside_b = dot(ray.direction, hit_b.normal) < 0.0;
+        }
+
+        if crossing.t > interval.max {
+            return false;
+        }
+
+        if csg_inside(object.data_idx, inside_a, inside_b) != inside {
+            *hit = crossing;
+            if !is_a && object.data_idx == CSG_DIFFERENCE {
+                (*hit).normal = -crossing.normal;
+            }
+
+            return true;
+        }
+
+        let next = Interval(crossing.t + CSG_EPSILON, CSG_MAX_DISTANCE);
+        if is_a {
+            has_a = objects_hit(a.x, a.y, ray, next, &hit_a);
+        } else {
+            has_b = objects_hit(b.x, b.y, ray, next, &hit_b);
+        }
+    }
+
+    return false;
+}
+
 fn material_scatter(material: Material, insert: Ray, hit: HitRecord) -> Ray {
     switch material.mat_type {
         case MAT_DIFFUSE: {
@@ -162,19 +773,21 @@ fn material_scatter(material: Material, insert: Ray, hit: HitRecord) -> Ray {
         }
 
         default: {
-            return Ray(vec3<f32>(0.0), vec3<f32>(0.0));
+            return Ray(vec3<f32>(0.0), vec3<f32>(0.0), insert.time);
         }
     }
 }
 
 fn diffuse_scatter(material: Material, insert: Ray, hit: HitRecord) -> Ray {
-    let diffused = hit.normal + rng_unit_sphere_f32();
-    return ray_new(hit.point, diffused);
+    // open surfaces like quads and disks can be hit from the back
+    let normal = faceForward(hit.normal, insert.direction, hit.normal);
+    let diffused = normal + rng_unit_sphere_f32();
+    return ray_new(hit.point, diffused, insert.time);
 }
 
 fn reflective_scatter(material: Material, insert: Ray, hit: HitRecord) -> Ray {
     let reflected = reflect(insert.direction, hit.normal);
-    return ray_new(hit.point, reflected + material.param1 * rng_unit_sphere_f32());
+    return ray_new(hit.point, reflected + material.param1 * rng_unit_sphere_f32(), insert.time);
 }
 
 fn transparent_scatter(material: Material, insert: Ray, hit: HitRecord) -> Ray {    
@@ -198,5 +811,5 @@ fn transparent_scatter(material: Material, insert: Ray, hit: HitRecord) -> Ray {
         direction = reflect(insert.direction, normal_out);
     }
 
-    return ray_new(hit.point, direction);
+    return ray_new(hit.point, direction, insert.time);
 }
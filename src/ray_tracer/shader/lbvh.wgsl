@@ -0,0 +1,406 @@
+/* constant-------------------------------------------------------------------*/
+const LBVH_WORKGROUP_SIZE: u32 = 256;
+// bits of each axis of the Morton codes
+const MORTON_BITS: u32 = 10;
+// the keys are sorted by digits of RADIX_BITS, and each workgroup sorts a
+// block of LBVH_ROUNDS rounds of one item per invocation
+const RADIX_BITS: u32 = 4;
+const RADIX: u32 = 1u << RADIX_BITS;
+const LBVH_ROUNDS: u32 = 16;
+const LBVH_BLOCK_SIZE: u32 = LBVH_WORKGROUP_SIZE * LBVH_ROUNDS;
+// bounds of the empty box, which the internal nodes are grown from
+const LBVH_EMPTY_MIN: f32 = 3.4e38;
+const LBVH_EMPTY_MAX: f32 = -3.4e38;
+// fixed point scale of the SAH cost summed by atomics, once per workgroup
+const LBVH_COST_SCALE: f32 = 65536.0;
+
+/* type-----------------------------------------------------------------------*/
+struct LbvhParam {
+    num_object: u32,
+    num_block: u32,
+    // of the digit sorted by the pass
+    shift: u32,
+}
+
+/* buffer---------------------------------------------------------------------*/
+@group(0) @binding(0) var<uniform> lbvh: LbvhParam;
+// boxes of each object at the start and the end of the shutter interval
+@group(0) @binding(1) var<storage, read> bboxes: array<AABB>;
+@group(0) @binding(2) var<storage, read_write> bvh: BVH;
+// Morton code and object index of each object, sorted from items_in to
+// items_out by each pass and swapped between the passes
+@group(0) @binding(3) var<storage, read_write> items_in: array<vec2<u32>>;
+@group(0) @binding(4) var<storage, read_write> items_out: array<vec2<u32>>;
+// count of each digit in each block, digit major, scanned to the offsets the
+// blocks scatter their items to
+@group(0) @binding(5) var<storage, read_write> histogram: array<u32>;
+// bounds of the centroids as ordered bits, min then max
+@group(0) @binding(6) var<storage, read_write> centroid_bounds: array<atomic<u32>, 6>;
+// SAH cost of the tree in fixed point, as computed by sah_cost in refit.rs, as
+// the low and the high words of a 64 bits counter
+@group(0) @binding(7) var<storage, read_write> cost: array<atomic<u32>, 2>;
+
+var<workgroup> digit_counts: array<atomic<u32>, RADIX>;
+var<workgroup> scan_sums: array<u32, LBVH_WORKGROUP_SIZE>;
+// counts of each digit up to each invocation, two 16 bits counts per word
+var<workgroup> digit_ranks: array<array<u32, 8>, LBVH_WORKGROUP_SIZE>;
+var<workgroup> digit_offsets: array<u32, RADIX>;
+var<workgroup> cost_sums: array<f32, LBVH_WORKGROUP_SIZE>;
+
+/* function-------------------------------------------------------------------*/
+@compute
+@workgroup_size(LBVH_WORKGROUP_SIZE, 1, 1)
+fn lbvh_bounds(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let i = global_id.x;
+    if i >= lbvh.num_object {
+        return;
+    }
+
+    let c = lbvh_centroid(i);
+    for (var axis = 0; axis < 3; axis++) {
+        atomicMin(&centroid_bounds[axis], lbvh_order(c[axis]));
+        atomicMax(&centroid_bounds[axis + 3], lbvh_order(c[axis]));
+    }
+}
+
+@compute
+@workgroup_size(LBVH_WORKGROUP_SIZE, 1, 1)
+fn lbvh_morton(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let i = global_id.x;
+    if i >= lbvh.num_object {
+        return;
+    }
+
+    var lower = vec3<f32>(0.0);
+    var upper = vec3<f32>(0.0);
+    for (var axis = 0; axis < 3; axis++) {
+        lower[axis] = lbvh_unorder(atomicLoad(&centroid_bounds[axis]));
+        upper[axis] = lbvh_unorder(atomicLoad(&centroid_bounds[axis + 3]));
+    }
+
+    // the centroids are quantized to a grid over their bounds
+    let extent = select(upper - lower, vec3<f32>(1.0), upper <= lower);
+    let scale = f32(1u << MORTON_BITS);
+    let unit = clamp((lbvh_centroid(i) - lower) / extent, vec3<f32>(0.0), vec3<f32>(1.0));
+    let cell = min(vec3<u32>(unit * scale), vec3<u32>((1u << MORTON_BITS) - 1u));
+    let code = (lbvh_spread(cell.x) << 2u) | (lbvh_spread(cell.y) << 1u) | lbvh_spread(cell.z);
+
+    items_in[i] = vec2<u32>(code, i);
+}
+
+// counts the digits of each block
+@compute
+@workgroup_size(LBVH_WORKGROUP_SIZE, 1, 1)
+fn lbvh_histogram(
+    @builtin(local_invocation_index) local_idx: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    if local_idx < RADIX {
+        atomicStore(&digit_counts[local_idx], 0u);
+    }
+    workgroupBarrier();
+
+    for (var round = 0u; round < LBVH_ROUNDS; round++) {
+        let i = workgroup_id.x * LBVH_BLOCK_SIZE + round * LBVH_WORKGROUP_SIZE + local_idx;
+        if i < lbvh.num_object {
+            atomicAdd(&digit_counts[lbvh_digit(items_in[i].x)], 1u);
+        }
+    }
+    workgroupBarrier();
+
+    if local_idx < RADIX {
+        histogram[local_idx * lbvh.num_block + workgroup_id.x] = atomicLoad(&digit_counts[local_idx]);
+    }
+}
+
+// exclusive prefix sum of the histogram by a single workgroup, each invocation
+// summing a contiguous chunk of it
+@compute
+@workgroup_size(LBVH_WORKGROUP_SIZE, 1, 1)
+fn lbvh_scan(@builtin(local_invocation_index) local_idx: u32) {
+    let len = RADIX * lbvh.num_block;
+    let chunk = (len + LBVH_WORKGROUP_SIZE - 1u) / LBVH_WORKGROUP_SIZE;
+    let start = local_idx * chunk;
+    let end = min(start + chunk, len);
+
+    var sum = 0u;
+    for (var i = start; i < end; i++) {
+        sum += histogram[i];
+    }
+    scan_sums[local_idx] = sum;
+    workgroupBarrier();
+
+    for (var offset = 1u; offset < LBVH_WORKGROUP_SIZE; offset <<= 1u) {
+        var value = scan_sums[local_idx];
+        if local_idx >= offset {
+            value += scan_sums[local_idx - offset];
+        }
+        workgroupBarrier();
+        scan_sums[local_idx] = value;
+        workgroupBarrier();
+    }
+
+    var prefix = scan_sums[local_idx] - sum;
+    for (var i = start; i < end; i++) {
+        let count = histogram[i];
+        histogram[i] = prefix;
+        prefix += count;
+    }
+}
+
+// moves the items of each block to the offsets of their digits, keeping their
+// order within each digit so that the sort is stable across the passes
+@compute
+@workgroup_size(LBVH_WORKGROUP_SIZE, 1, 1)
+fn lbvh_scatter(
+    @builtin(local_invocation_index) local_idx: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    if local_idx < RADIX {
+        digit_offsets[local_idx] = histogram[local_idx * lbvh.num_block + workgroup_id.x];
+    }
+    workgroupBarrier();
+
+    for (var round = 0u; round < LBVH_ROUNDS; round++) {
+        let round_start = workgroup_id.x * LBVH_BLOCK_SIZE + round * LBVH_WORKGROUP_SIZE;
+        if round_start >= lbvh.num_object {
+            break;
+        }
+
+        let i = round_start + local_idx;
+        let valid = i < lbvh.num_object;
+        var item = vec2<u32>(0u);
+        var digit = 0u;
+        var counts = array<u32, 8>();
+        if valid {
+            item = items_in[i];
+            digit = lbvh_digit(item.x);
+            counts[digit >> 1u] = 1u << ((digit & 1u) * 16u);
+        }
+
+        // inclusive prefix sum of the counts over the invocations
+        digit_ranks[local_idx] = counts;
+        workgroupBarrier();
+        for (var offset = 1u; offset < LBVH_WORKGROUP_SIZE; offset <<= 1u) {
+            var value = digit_ranks[local_idx];
+            if local_idx >= offset {
+                let other = digit_ranks[local_idx - offset];
+                for (var k = 0; k < 8; k++) {
+                    value[k] += other[k];
+                }
+            }
+            workgroupBarrier();
+            digit_ranks[local_idx] = value;
+            workgroupBarrier();
+        }
+
+        if valid {
+            let rank = lbvh_count(digit_ranks[local_idx], digit) - 1u;
+            items_out[digit_offsets[digit] + rank] = item;
+        }
+        workgroupBarrier();
+
+        if local_idx < RADIX {
+            digit_offsets[local_idx] += lbvh_count(digit_ranks[LBVH_WORKGROUP_SIZE - 1u], local_idx);
+        }
+        workgroupBarrier();
+    }
+}
+
+// emits the radix tree over the sorted codes [Karras 2012], with the internal
+// nodes first so that the root is the first node, and the leaves after them
+@compute
+@workgroup_size(LBVH_WORKGROUP_SIZE, 1, 1)
+fn lbvh_hierarchy(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let n = i32(lbvh.num_object);
+    let i = i32(global_id.x);
+    if i >= n {
+        return;
+    }
+
+    if i == 0 {
+        bvh.num_node = u32(2 * n - 1);
+    }
+
+    let object_idx = items_in[i].y;
+    let leaf = &bvh.nodes[n - 1 + i];
+    (*leaf).bbox = bboxes[2u * object_idx];
+    (*leaf).bbox_end = bboxes[2u * object_idx + 1u];
+    (*leaf).left_idx = -1;
+    (*leaf).right_idx = -1;
+    (*leaf).object_idx = i32(object_idx);
+
+    if i >= n - 1 {
+        return;
+    }
+
+    // the range of the node extends to the side sharing the longer prefix
+    let d = select(-1, 1, lbvh_delta(i, i + 1) > lbvh_delta(i, i - 1));
+    let delta_min = lbvh_delta(i, i - d);
+
+    var length_max = 2;
+    while lbvh_delta(i, i + length_max * d) > delta_min {
+        length_max *= 2;
+    }
+    var length = 0;
+    for (var t = length_max / 2; t >= 1; t /= 2) {
+        if lbvh_delta(i, i + (length + t) * d) > delta_min {
+            length += t;
+        }
+    }
+    let j = i + length * d;
+
+    // split where the prefix of the range ends
+    let delta_node = lbvh_delta(i, j);
+    var split = 0;
+    var t = length;
+    loop {
+        t = (t + 1) >> 1u;
+        if lbvh_delta(i, i + (split + t) * d) > delta_node {
+            split += t;
+        }
+        if t <= 1 {
+            break;
+        }
+    }
+    let gamma = i + split * d + min(d, 0);
+
+    let node = &bvh.nodes[i];
+    (*node).bbox = AABB(vec3<f32>(LBVH_EMPTY_MIN), vec3<f32>(LBVH_EMPTY_MAX));
+    (*node).bbox_end = (*node).bbox;
+    (*node).left_idx = select(gamma, n - 1 + gamma, min(i, j) == gamma);
+    (*node).right_idx = select(gamma + 1, n + gamma, max(i, j) == gamma + 1);
+    (*node).object_idx = -1;
+}
+
+// grows every internal node to its children, repeated once per level of the
+// tree so that the bounds reach the root from the deepest leaves
+@compute
+@workgroup_size(LBVH_WORKGROUP_SIZE, 1, 1)
+fn lbvh_fit(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let i = global_id.x;
+    if i + 1u >= lbvh.num_object {
+        return;
+    }
+
+    let node = &bvh.nodes[i];
+    let left = bvh.nodes[(*node).left_idx];
+    let right = bvh.nodes[(*node).right_idx];
+    (*node).bbox = lbvh_union(left.bbox, right.bbox);
+    (*node).bbox_end = lbvh_union(left.bbox_end, right.bbox_end);
+}
+
+// updates the leaves to the boxes of their objects, for the tree to be
+// fitted to them again with its topology kept
+@compute
+@workgroup_size(LBVH_WORKGROUP_SIZE, 1, 1)
+fn lbvh_leaves(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let i = global_id.x;
+    if i >= lbvh.num_object {
+        return;
+    }
+
+    let leaf = &bvh.nodes[lbvh.num_object - 1u + i];
+    let object_idx = u32((*leaf).object_idx);
+    (*leaf).bbox = bboxes[2u * object_idx];
+    (*leaf).bbox_end = bboxes[2u * object_idx + 1u];
+}
+
+// sums the areas of the nodes relative to the root, each invocation adding
+// an internal node and a leaf, reduced within the workgroup before rounding so
+// that the areas of the deep nodes are not lost to the fixed point
+@compute
+@workgroup_size(LBVH_WORKGROUP_SIZE, 1, 1)
+fn lbvh_cost(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_idx: u32,
+) {
+    let i = global_id.x;
+    var area = 0.0;
+    if i < lbvh.num_object {
+        let root_area = max(lbvh_area(bvh.nodes[0]), 1.0e-30);
+        area = lbvh_area(bvh.nodes[lbvh.num_object - 1u + i]);
+        if i + 1u < lbvh.num_object {
+            area += lbvh_area(bvh.nodes[i]);
+        }
+        area /= root_area;
+    }
+    cost_sums[local_idx] = area;
+    workgroupBarrier();
+
+    for (var offset = LBVH_WORKGROUP_SIZE / 2u; offset > 0u; offset >>= 1u) {
+        if local_idx < offset {
+            cost_sums[local_idx] += cost_sums[local_idx + offset];
+        }
+        workgroupBarrier();
+    }
+
+    if local_idx == 0u {
+        // carry into the high word when the low word wraps around
+        let n = u32(round(cost_sums[0] * LBVH_COST_SCALE));
+        let low = atomicAdd(&cost[0], n);
+        if low + n < low {
+            atomicAdd(&cost[1], 1u);
+        }
+    }
+}
+
+fn lbvh_centroid(i: u32) -> vec3<f32> {
+    let bbox = lbvh_union(bboxes[2u * i], bboxes[2u * i + 1u]);
+    return (bbox.min + bbox.max) / 2.0;
+}
+
+fn lbvh_union(a: AABB, b: AABB) -> AABB {
+    return AABB(min(a.min, b.min), max(a.max, b.max));
+}
+
+// surface area of the box swept by the node within the shutter interval
+fn lbvh_area(node: BVHNode) -> f32 {
+    let bbox = lbvh_union(node.bbox, node.bbox_end);
+    let d = bbox.max - bbox.min;
+    return 2.0 * (d.x * d.y + d.y * d.z + d.z * d.x);
+}
+
+// bits of a float which compare as unsigned integers in the order of the
+// floats, so that the bounds can be found by atomics
+fn lbvh_order(x: f32) -> u32 {
+    let bits = bitcast<u32>(x);
+    return select(bits | 0x80000000u, ~bits, (bits & 0x80000000u) != 0u);
+}
+
+fn lbvh_unorder(bits: u32) -> f32 {
+    return bitcast<f32>(select(~bits, bits & 0x7fffffffu, (bits & 0x80000000u) != 0u));
+}
+
+// spreads the bits of x to every third bit
+fn lbvh_spread(x: u32) -> u32 {
+    var v = x & 0x3ffu;
+    v = (v | (v << 16u)) & 0x030000ffu;
+    v = (v | (v << 8u)) & 0x0300f00fu;
+    v = (v | (v << 4u)) & 0x030c30c3u;
+    v = (v | (v << 2u)) & 0x09249249u;
+    return v;
+}
+
+fn lbvh_digit(code: u32) -> u32 {
+    return (code >> lbvh.shift) & (RADIX - 1u);
+}
+
+fn lbvh_count(counts: array<u32, 8>, digit: u32) -> u32 {
+    return (counts[digit >> 1u] >> ((digit & 1u) * 16u)) & 0xffffu;
+}
+
+// length of the common prefix of the codes of the sorted items i and j, with
+// equal codes told apart by their indices, or -1 if j is out of range
+fn lbvh_delta(i: i32, j: i32) -> i32 {
+    if j < 0 || j >= i32(lbvh.num_object) {
+        return -1;
+    }
+
+    let a = items_in[i].x;
+    let b = items_in[j].x;
+    if a == b {
+        return 32 + i32(countLeadingZeros(u32(i) ^ u32(j)));
+    }
+    return i32(countLeadingZeros(a ^ b));
+}
@@ -0,0 +1,35 @@
+/* texture--------------------------------------------------------------------*/
+// static triangles of the top level objects, one per object in their order
+// with the other objects left out as inactive triangles
+@group(2) @binding(2) var acceleration_structure: acceleration_structure;
+
+/* function-------------------------------------------------------------------*/
+// hardware traversal of the triangles, with the rest of the objects through
+// the BVH up to the closest triangle
+fn ray_query_hit(ray: Ray, _interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    var interval = _interval;
+    var found = false;
+
+    var query: ray_query;
+    rayQueryInitialize(&query, acceleration_structure, RayDesc(RAY_FLAG_FORCE_OPAQUE, 0xffu,
+        interval.min, interval.max, ray.origin, ray.direction));
+    rayQueryProceed(&query);
+
+    let intersection = rayQueryGetCommittedIntersection(&query);
+    if intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE {
+        let object_idx = i32(intersection.primitive_index);
+        (*hit).point = ray_at(ray, intersection.t);
+        (*hit).t = intersection.t;
+        (*hit).normal = triangle_normal(objects.objects[object_idx], intersection.barycentrics);
+        (*hit).object_idx = object_idx;
+
+        interval.max = intersection.t;
+        found = true;
+    }
+
+    return bvh_hit(ray, interval, hit) || found;
+}
+
+fn ray_query_contains(object: Object) -> bool {
+    return object.obj_type == OBJ_TRIANGLE && object.motion_idx < 0;
+}
@@ -0,0 +1,9 @@
+/* function-------------------------------------------------------------------*/
+// without ray queries on the device everything is hit through the BVH
+fn ray_query_hit(ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    return bvh_hit(ray, interval, hit);
+}
+
+fn ray_query_contains(object: Object) -> bool {
+    return false;
+}
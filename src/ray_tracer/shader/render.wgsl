@@ -19,7 +19,7 @@ const FULL_SCREEN_QUAD = array<vec4<f32>, 3>(
 @group(0) @binding(1) var<uniform> param: Param;
 
 /* buffer---------------------------------------------------------------------*/
-@group(1) @binding(0) var<storage, read_write> frame: array<vec3<f32>>;
+@group(1) @binding(0) var<storage, read_write> frame: array<Pixel>;
 
 /* function-------------------------------------------------------------------*/
 @vertex
@@ -32,7 +32,72 @@ fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let idx = u32(in.tex_coord.x * f32(param.window_size.x)) +
-        u32(in.tex_coord.y * f32(param.window_size.y)) * param.window_size.x + 1;
-    return vec4<f32>(sqrt(frame[idx] / f32(stat.frame_counter + 1)), 1.0);
+    let size = vec2<f32>(param.window_size);
+
+    var color: vec3<f32>;
+    switch param.display_filter {
+        case DISPLAY_BILINEAR, DISPLAY_BICUBIC: {
+            color = filtered_color(in.tex_coord * size - 0.5);
+        }
+
+        default: {
+            color = texel_color(vec2<i32>(floor(in.tex_coord * size)));
+        }
+    }
+
+    return vec4<f32>(sqrt(color), 1.0);
+}
+
+// frame buffer resampled around a position with texel centers at integers,
+// the kernel is widened by the render scale when downsampling so that every
+// texel under the display pixel is weighted in
+fn filtered_color(p: vec2<f32>) -> vec3<f32> {
+    var kernel_radius = 1.0;
+    if param.display_filter == DISPLAY_BICUBIC {
+        kernel_radius = 2.0;
+    }
+    let scale = max(param.render_scale, 1.0);
+    let radius = kernel_radius * scale;
+
+    let lo = vec2<i32>(floor(p - radius)) + 1;
+    let hi = vec2<i32>(floor(p + radius));
+    var color = vec3<f32>(0.0, 0.0, 0.0);
+    var weight = 0.0;
+    for (var y = lo.y; y <= hi.y; y++) {
+        let wy = display_kernel((f32(y) - p.y) / scale);
+        for (var x = lo.x; x <= hi.x; x++) {
+            let w = display_kernel((f32(x) - p.x) / scale) * wy;
+            color += w * texel_color(vec2<i32>(x, y));
+            weight += w;
+        }
+    }
+
+    // bicubic overshoots at edges
+    return max(color / weight, vec3<f32>(0.0));
+}
+
+// tent for bilinear and Catmull-Rom for bicubic
+fn display_kernel(x: f32) -> f32 {
+    let t = abs(x);
+    if param.display_filter == DISPLAY_BICUBIC {
+        if t < 1.0 {
+            return 1.5 * t * t * t - 2.5 * t * t + 1.0;
+        } else if t < 2.0 {
+            return -0.5 * t * t * t + 2.5 * t * t - 4.0 * t + 2.0;
+        }
+        return 0.0;
+    }
+    return max(1.0 - t, 0.0);
+}
+
+// resolved color of a texel, clamped to the edges
+fn texel_color(texel: vec2<i32>) -> vec3<f32> {
+    let clamped = vec2<u32>(clamp(texel, vec2<i32>(0), vec2<i32>(param.window_size) - 1));
+    let pixel = frame[clamped.x + clamped.y * param.window_size.x];
+
+    // filters with negative lobes can leave a pixel without weight early on
+    if pixel.weight <= 0.0 {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+    return max(param.camera.exposure * pixel.color / pixel.weight, vec3<f32>(0.0));
 }
@@ -0,0 +1,85 @@
+/* constant-------------------------------------------------------------------*/
+const SDF_MAX_STEP: u32 = 256;
+const SDF_EPSILON: f32 = 0.0001;
+const SDF_MAX_DISTANCE: f32 = 1e9;
+
+/* function-------------------------------------------------------------------*/
+// distance functions from https://iquilezles.org/articles/distfunctions
+fn sdf_sphere(p: vec3<f32>, radius: f32) -> f32 {
+    return length(p) - radius;
+}
+
+fn sdf_box(p: vec3<f32>, half_extent: vec3<f32>) -> f32 {
+    let q = abs(p) - half_extent;
+    return length(max(q, vec3<f32>(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+}
+
+fn sdf_torus(p: vec3<f32>, radius: vec2<f32>) -> f32 {
+    let q = vec2<f32>(length(p.xz) - radius.x, p.y);
+    return length(q) - radius.y;
+}
+
+fn sdf_capsule(p: vec3<f32>, a: vec3<f32>, b: vec3<f32>, radius: f32) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+    return length(pa - ba * h) - radius;
+}
+
+// polynomial smooth minimum
+fn sdf_smooth_union(a: f32, b: f32, k: f32) -> f32 {
+    let h = clamp(0.5 + 0.5 * (b - a) / k, 0.0, 1.0);
+    return mix(b, a, h) - k * h * (1.0 - h);
+}
+
+// limited domain repetition, count is the number of repetitions to each side
+fn sdf_repeat(p: vec3<f32>, period: vec3<f32>, count: vec3<f32>) -> vec3<f32> {
+    return p - period * clamp(round(p / period), -count, count);
+}
+
+// https://iquilezles.org/articles/normalsSDF
+fn sdf_normal(idx: u32, p: vec3<f32>) -> vec3<f32> {
+    let k = vec2<f32>(1.0, -1.0);
+    let h = SDF_EPSILON;
+    return normalize(k.xyy * sdf_eval(idx, p + k.xyy * h) +
+        k.yyx * sdf_eval(idx, p + k.yyx * h) +
+        k.yxy * sdf_eval(idx, p + k.yxy * h) +
+        k.xxx * sdf_eval(idx, p + k.xxx * h));
+}
+
+// sphere tracing inside the bounding box of the object, rays starting on the
+// surface (e.g. after scattering) first step off it before the side they march
+// on is decided, so that both the outside and the inside can be hit
+fn sdf_hit(object: Object, ray: Ray, interval: Interval, hit: ptr<function, HitRecord>) -> bool {
+    let t0 = (object.v[0] - ray.origin) / ray.direction;
+    let t1 = (object.v[1] - ray.origin) / ray.direction;
+    let tmin = min(t0, t1);
+    let tmax = max(t0, t1);
+    var t = max(interval.min, max(tmin.x, max(tmin.y, tmin.z)));
+    let t_end = min(interval.max, min(tmax.x, min(tmax.y, tmax.z)));
+
+    var side = 0.0;
+    for (var i = 0u; i < SDF_MAX_STEP && t <= t_end; i++) {
+        let distance = sdf_eval(object.data_idx, ray_at(ray, t));
+
+        if side == 0.0 {
+            if abs(distance) < SDF_EPSILON {
+                t += SDF_EPSILON;
+                continue;
+            }
+            side = sign(distance);
+        }
+
+        if side * distance < SDF_EPSILON {
+            (*hit).point = ray_at(ray, t);
+            (*hit).t = t;
+            (*hit).normal = sdf_normal(object.data_idx, (*hit).point);
+
+            return true;
+        }
+
+        t += side * distance;
+    }
+
+    return false;
+}
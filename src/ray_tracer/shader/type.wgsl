@@ -1,18 +1,62 @@
 const HIT_BRUTE: u32 = 0;
 const HIT_BVH: u32 = 1;
+const HIT_RAY_QUERY: u32 = 2;
 
 const SHADE_FLAT: u32 = 0;
 const SHADE_SMOOTH: u32 = 1;
 
+const SAMPLER_PCG: u32 = 0;
+const SAMPLER_SOBOL: u32 = 1;
+const SAMPLER_BLUE_NOISE: u32 = 2;
+
+const FILTER_BOX: u32 = 0;
+const FILTER_GAUSSIAN: u32 = 1;
+const FILTER_MITCHELL: u32 = 2;
+const FILTER_BLACKMAN_HARRIS: u32 = 3;
+const FILTER_LANCZOS: u32 = 4;
+
+const DISPLAY_NEAREST: u32 = 0;
+const DISPLAY_BILINEAR: u32 = 1;
+const DISPLAY_BICUBIC: u32 = 2;
+
+const APERTURE_CIRCLE: u32 = 0;
+const APERTURE_POLYGON: u32 = 1;
+const APERTURE_MASK: u32 = 2;
+
+const PROJ_PERSPECTIVE: u32 = 0;
+const PROJ_ORTHOGRAPHIC: u32 = 1;
+const PROJ_FISHEYE: u32 = 2;
+const PROJ_EQUIRECTANGULAR: u32 = 3;
+const PROJ_CYLINDRICAL: u32 = 4;
+
 const OBJ_TRIANGLE: u32 = 0;
 const OBJ_SPHERE: u32 = 1;
+const OBJ_QUAD: u32 = 2;
+const OBJ_BOX: u32 = 3;
+const OBJ_DISK: u32 = 4;
+const OBJ_CYLINDER: u32 = 5;
+const OBJ_CONE: u32 = 6;
+const OBJ_SDF: u32 = 7;
+const OBJ_CSG: u32 = 8;
+
+const CSG_UNION: u32 = 0;
+const CSG_INTERSECTION: u32 = 1;
+const CSG_DIFFERENCE: u32 = 2;
+
+const MAX_KEYFRAME: u32 = 8;
 
 const MAT_DIFFUSE: u32 = 0;
 const MAT_REFLECTIVE: u32 = 1;
 const MAT_TRANSPARENT: u32 = 2;
 
 struct Stat {
-    frame_counter: u32,
+    // index of the first sample of this dispatch in the random sequences,
+    // continued across reprojections
+    sample_index: u32,
+    // samples taken by each pixel in this dispatch
+    num_sample: u32,
+    // whether the accumulation starts over with this dispatch
+    clear: u32,
 }
 
 struct Param {
@@ -21,6 +65,47 @@ struct Param {
     hit_algorithm: u32,
     shading_algorithm: u32,
     max_bounce: u32,
+    sampler_type: u32,
+    // relative error at which pixels stop being sampled, 0 to disable
+    noise_threshold: f32,
+    filter_type: u32,
+    // in pixels
+    filter_radius: f32,
+    // rendered pixels per displayed pixel along each axis
+    render_scale: f32,
+    display_filter: u32,
+    // position of the frame buffer in the whole image and its size, for the
+    // random sequences of the pixels
+    pixel_offset: vec2<u32>,
+    image_size: vec2<u32>,
+    // region of the frame buffer being dispatched, the rest keeps its image
+    dispatch_offset: vec2<u32>,
+    dispatch_size: vec2<u32>,
+}
+
+// accumulation of a pixel in the frame buffer
+struct Pixel {
+    // sum of the samples weighted by the reconstruction filter
+    color: vec3<f32>,
+    // sum of the filter weights, which may be negative for filters with
+    // negative lobes
+    weight: f32,
+    num_sample: f32,
+    // sum of the luminance and the squared luminance of the unweighted samples
+    lum: f32,
+    moment: f32,
+}
+
+struct Convergence {
+    // pixels still being sampled
+    num_active: atomic<u32>,
+    // sum of the relative errors of all pixels in units of 1/256, each
+    // clamped to 1, as the low and the high words of a 64 bits counter
+    error_sum_low: atomic<u32>,
+    error_sum_high: atomic<u32>,
+    // rays traced, as the low and the high words of a 64 bits counter
+    num_ray_low: atomic<u32>,
+    num_ray_high: atomic<u32>,
 }
 
 struct CameraParam {
@@ -35,6 +120,28 @@ struct CameraParam {
     vy: vec3<f32>,
 
     lens_radius: f32,
+    // time of the shutter open and close
+    shutter: vec2<f32>,
+
+    aperture_shape: u32,
+    aperture_blades: u32,
+    // in radians
+    aperture_rotation: f32,
+    cat_eye: f32,
+
+    // viewing direction, for the projections not defined by the focus plane
+    forward: vec3<f32>,
+    projection: u32,
+    // vertical field of view in radians
+    fov: f32,
+    aspect_ratio: f32,
+    focus_distance: f32,
+    // multiplier of the accumulated radiance before display
+    exposure: f32,
+    // part of the image covered by the frame buffer in texture coordinates,
+    // for rendering in tiles
+    window_min: vec2<f32>,
+    window_size: vec2<f32>,
 }
 
 struct AABB {
@@ -48,13 +155,16 @@ struct BVH {
 }
 
 struct BVHNode {
+    // bounding boxes at the start and the end of the shutter interval
     bbox: AABB,
+    bbox_end: AABB,
     left_idx: i32,
     right_idx: i32,
     object_idx: i32,
 }
 
 struct Objects {
+    // number of top level objects, CSG operands are stored after them
     num_object: u32,
     objects: array<Object>,
 }
@@ -62,10 +172,24 @@ struct Objects {
 struct Object {
     obj_type: u32,
     mat_idx: u32,
+    data_idx: u32,
+    motion_idx: i32,
     v: array<vec3<f32>, 3>,
     n: array<vec3<f32>, 3>,
 }
 
+struct Motions {
+    num_motion: u32,
+    motions: array<Motion>,
+}
+
+// keyframed transforms, interpolated linearly in between
+struct Motion {
+    num_keyframe: u32,
+    times: array<f32, MAX_KEYFRAME>,
+    transforms: array<mat4x4<f32>, MAX_KEYFRAME>,
+}
+
 struct Materials {
     num_material: u32,
     materials: array<Material>,
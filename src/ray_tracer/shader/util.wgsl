@@ -1,18 +1,62 @@
 const PI = 3.1415927f;
 
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
 fn schlick(cos: f32, ref_idx: f32) -> f32 {
     var r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     r0 = r0 * r0;
     return r0 + pow((1.0 - r0) * (1.0 - cos), 5.0);
 }
 
+fn sinc(x: f32) -> f32 {
+    if abs(x) < 1e-5 {
+        return 1.0;
+    }
+    return sin(PI * x) / (PI * x);
+}
+
 // random number generator adapted from [Nelarius/weekend-raytracer-wgpu]
 // (https://github.com/Nelarius/weekend-raytracer-wgpu/blob/main/src/raytracer/raytracer.wgsl#L531)
 var<private> _rng: u32;
 
-fn rng_init(pixel: vec2<u32>, resolution: vec2<u32>, frame: u32) {
-    let seed = dot(pixel, vec2<u32>(1u, resolution.x)) ^ hash(frame);
+// sampler state, each call of rng_f32 takes the next dimension of the sample
+var<private> _sampler_type: u32;
+var<private> _sample_pixel: vec2<u32>;
+var<private> _sample_index: u32;
+var<private> _sample_dim: u32;
+var<private> _sample_seed: u32;
+
+const BLUE_NOISE_SIZE: u32 = 64;
+
+fn rng_init(pixel: vec2<u32>, resolution: vec2<u32>, index: u32, sampler_type: u32) {
+    let seed = dot(pixel, vec2<u32>(1u, resolution.x)) ^ hash(index);
     _rng = hash(seed);
+
+    _sampler_type = sampler_type;
+    _sample_pixel = pixel;
+    _sample_index = index;
+    _sample_dim = 0u;
+    // the blue noise sampler shares the sequence between pixels and only
+    // offsets it by the dither texture
+    if sampler_type == SAMPLER_BLUE_NOISE {
+        _sample_seed = 0x2545f491u;
+    } else {
+        _sample_seed = hash(dot(pixel, vec2<u32>(1u, resolution.x)) + 0x68bc21ebu);
+    }
+}
+
+// state of the sampler within the sample, to be resumed by another kernel
+fn rng_state() -> vec2<u32> {
+    return vec2<u32>(_rng, _sample_dim);
+}
+
+fn rng_resume(pixel: vec2<u32>, resolution: vec2<u32>, index: u32, sampler_type: u32,
+    state: vec2<u32>) {
+    rng_init(pixel, resolution, index, sampler_type);
+    _rng = state.x;
+    _sample_dim = state.y;
 }
 
 fn rng_u32() -> u32 {
@@ -22,14 +66,81 @@ fn rng_u32() -> u32 {
 }
 
 fn rng_range_u32(min: u32, max: u32) -> u32 {
-    return rng_u32() % (max - min) + min;
+    return min(u32(rng_f32() * f32(max - min)), max - min - 1u) + min;
 }
 
-fn rng_f32() -> f32 {
+fn pcg_f32() -> f32 {
     let x = rng_u32();
     return f32(x) / f32(0xffffffffu);
 }
 
+// next dimension of the sample in [0, 1)
+fn rng_f32() -> f32 {
+    let dim = _sample_dim;
+    _sample_dim++;
+
+    switch _sampler_type {
+        case SAMPLER_SOBOL: {
+            return sobol_owen_f32(_sample_index, dim, _sample_seed);
+        }
+        case SAMPLER_BLUE_NOISE: {
+            // Cranley-Patterson rotation by the dither texture, offset
+            // differently for each dimension
+            let offset = vec2<u32>(hash(dim), hash(dim + 0x9e3779b9u));
+            let texel = (_sample_pixel + offset) % BLUE_NOISE_SIZE;
+            let rotation = textureLoad(blue_noise, texel, 0).r + 0.5 / 256.0;
+            return fract(sobol_owen_f32(_sample_index, dim, _sample_seed) + rotation);
+        }
+        case SAMPLER_PCG, default: {
+            return pcg_f32();
+        }
+    }
+}
+
+// Owen-scrambled Sobol sequence by hashing [Burley 2020, Practical Hash-based
+// Owen Scrambling], dimensions are padded from pairs of the first two Sobol
+// dimensions, with the sample index shuffled for each pair
+fn sobol_owen_f32(index: u32, dim: u32, seed: u32) -> f32 {
+    let pair = dim / 2u;
+    let shuffled = nested_uniform_scramble(index, hash(seed ^ hash(pair)));
+
+    var x: u32;
+    if dim % 2u == 0u {
+        x = reverseBits(shuffled);
+    } else {
+        x = sobol_dim1(shuffled);
+    }
+    x = nested_uniform_scramble(x, hash(seed ^ hash(dim + 0x85ebca6bu)));
+
+    return f32(x >> 8u) / 16777216.0;
+}
+
+fn sobol_dim1(index: u32) -> u32 {
+    var result = 0u;
+    var v = 1u << 31u;
+    for (var i = index; i != 0u; i >>= 1u) {
+        if (i & 1u) != 0u {
+            result ^= v;
+        }
+        v ^= v >> 1u;
+    }
+    return result;
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    return reverseBits(laine_karras_permutation(reverseBits(x), seed));
+}
+
+fn laine_karras_permutation(_x: u32, seed: u32) -> u32 {
+    var x = _x;
+    x ^= x * 0x3d20adeau;
+    x += seed;
+    x *= (seed >> 16u) | 1u;
+    x ^= x * 0x05526c56u;
+    x ^= x * 0x53a22864u;
+    return x;
+}
+
 fn rng_unit_disk_f32() -> vec2<f32> {
     let r = sqrt(rng_f32());
     let alpha = 2 * PI * rng_f32();
@@ -38,6 +149,19 @@ fn rng_unit_disk_f32() -> vec2<f32> {
     return vec2<f32>(x, y);
 }
 
+// uniform point in a regular polygon inscribed in the unit circle, by picking
+// one of the equal triangles between the center and an edge
+fn rng_unit_polygon_f32(sides: u32, rotation: f32) -> vec2<f32> {
+    let n = max(sides, 3u);
+    let side = f32(rng_range_u32(0u, n));
+    let alpha0 = rotation + 2 * PI * side / f32(n);
+    let alpha1 = rotation + 2 * PI * (side + 1) / f32(n);
+
+    let r = sqrt(rng_f32());
+    let s = rng_f32();
+    return r * ((1 - s) * vec2<f32>(cos(alpha0), sin(alpha0)) + s * vec2<f32>(cos(alpha1), sin(alpha1)));
+}
+
 fn rng_unit_sphere_f32() -> vec3<f32> {
     let r = pow(rng_f32(), 0.33333f);
     let cosTheta = 1 - 2 * rng_f32();
@@ -0,0 +1,321 @@
+/* constant-------------------------------------------------------------------*/
+const WAVEFRONT_WORKGROUP_SIZE: u32 = 64;
+const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+
+// paths to be intersected, paths hitting each material to be shaded, and
+// shadow rays to be tested
+const QUEUE_EXTEND: u32 = 0;
+const QUEUE_DIFFUSE: u32 = 1;
+const QUEUE_REFLECTIVE: u32 = 2;
+const QUEUE_TRANSPARENT: u32 = 3;
+const QUEUE_SHADOW: u32 = 4;
+const NUM_QUEUE: u32 = 5;
+
+// the pixel is still being sampled
+const PATH_ACTIVE: u32 = 1;
+// the background seen by the next ray is already sampled by a shadow ray
+const PATH_SHADOWED: u32 = 2;
+
+/* type-----------------------------------------------------------------------*/
+// sample of a pixel carried between the kernels
+struct Path {
+    origin: vec3<f32>,
+    time: f32,
+    direction: vec3<f32>,
+    // rays intersected so far
+    bounce: u32,
+    throughput: vec3<f32>,
+    // of the reconstruction filter
+    weight: f32,
+    radiance: vec3<f32>,
+    flags: u32,
+    hit_normal: vec3<f32>,
+    hit_t: f32,
+    shadow_direction: vec3<f32>,
+    hit_object: i32,
+    // sampler state to resume the random sequence of the sample
+    rng: vec2<u32>,
+}
+
+// queues of path indices, appended through next and consumed up to size,
+// which are swapped by wf_queues between the kernels
+struct Queues {
+    next: array<atomic<u32>, NUM_QUEUE>,
+    size: array<u32, NUM_QUEUE>,
+    // sample of the dispatch being traced
+    sample: u32,
+    items: array<u32>,
+}
+
+/* buffer---------------------------------------------------------------------*/
+@group(3) @binding(0) var<storage, read_write> queues: Queues;
+@group(3) @binding(1) var<storage, read_write> paths: array<Path>;
+// arguments of the indirect dispatches over the queues, bound by wf_queues
+// only as they cannot be written and used in the same dispatch
+@group(3) @binding(1) var<storage, read_write> dispatch_args: array<vec3<u32>, NUM_QUEUE>;
+
+/* function-------------------------------------------------------------------*/
+// starts a sample of every pixel in the dispatch, as cs_main does before the
+// bounces
+@compute
+@workgroup_size(WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, 1)
+fn wf_generate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if any(global_id.xy >= param.dispatch_size) {
+        return;
+    }
+    let id = global_id.xy + param.dispatch_offset;
+    let path_idx = global_id.x + global_id.y * param.dispatch_size.x;
+    let path = &paths[path_idx];
+
+    // whether the pixel is sampled is decided once per dispatch
+    if queues.sample == 0u {
+        let pixel = &frame[id.x + id.y * param.window_size.x];
+        if stat.clear != 0u {
+            *pixel = pixel_new();
+        }
+
+        let error = pixel_error(*pixel);
+        sum_error(u32(min(error, 1.0) * 256.0));
+        if param.noise_threshold > 0.0 && (*pixel).num_sample >= ADAPTIVE_MIN_SAMPLE
+            && error < param.noise_threshold {
+            (*path).flags = 0u;
+            return;
+        }
+        atomicAdd(&convergence.num_active, 1u);
+    } else if ((*path).flags & PATH_ACTIVE) == 0u {
+        return;
+    }
+
+    rng_init(id + param.pixel_offset, param.image_size, stat.sample_index + queues.sample,
+        param.sampler_type);
+
+    var ray: Ray;
+    var weight: f32;
+    let has_ray = pixel_sample_ray(id, &ray, &weight);
+    *path = Path(ray.origin, ray.time, ray.direction, 0u, vec3<f32>(1.0, 1.0, 1.0), weight,
+        vec3<f32>(0.0, 0.0, 0.0), PATH_ACTIVE, vec3<f32>(0.0, 0.0, 0.0), 0.0,
+        vec3<f32>(0.0, 0.0, 0.0), -1, rng_state());
+    if has_ray {
+        queue_push(QUEUE_EXTEND, path_idx);
+    }
+}
+
+// moves what was appended to the queues into their sizes and the arguments
+// of the dispatches over them, the rays queued are counted as traced
+@compute
+@workgroup_size(1, 1, 1)
+fn wf_queues() {
+    for (var q = 0u; q < NUM_QUEUE; q++) {
+        let size = atomicExchange(&queues.next[q], 0u);
+        queues.size[q] = size;
+
+        let num_workgroup = div_ceil(size, WAVEFRONT_WORKGROUP_SIZE);
+        let x = min(num_workgroup, MAX_WORKGROUPS_PER_DIMENSION);
+        dispatch_args[q] = vec3<u32>(x, div_ceil(num_workgroup, max(x, 1u)), 1u);
+
+        if q == QUEUE_EXTEND || q == QUEUE_SHADOW {
+            count_rays(size);
+        }
+    }
+}
+
+// closest hits of the extended paths, sorted into the queues of their
+// materials, escaping paths take the background
+@compute
+@workgroup_size(WAVEFRONT_WORKGROUP_SIZE, 1, 1)
+fn wf_intersect(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let i = id.x + id.y * num_workgroups.x * WAVEFRONT_WORKGROUP_SIZE;
+    if i >= queues.size[QUEUE_EXTEND] {
+        return;
+    }
+    let path_idx = queue_item(QUEUE_EXTEND, i);
+    let path = &paths[path_idx];
+
+    let ray = Ray((*path).origin, (*path).direction, (*path).time);
+    var hit: HitRecord;
+    if !calculate_hit(ray, Interval(0.001, 1000.0), &hit) {
+        if ((*path).flags & PATH_SHADOWED) == 0u {
+            (*path).radiance += (*path).throughput * background(ray.direction);
+        }
+        return;
+    }
+
+    // a path ending at a surface carries nothing, as in color
+    (*path).bounce += 1u;
+    if (*path).bounce >= param.max_bounce {
+        return;
+    }
+
+    (*path).hit_normal = hit.normal;
+    (*path).hit_t = hit.t;
+    (*path).hit_object = hit.object_idx;
+
+    let material = materials.materials[objects.objects[hit.object_idx].mat_idx];
+    switch material.mat_type {
+        case MAT_DIFFUSE: {
+            queue_push(QUEUE_DIFFUSE, path_idx);
+        }
+        case MAT_REFLECTIVE: {
+            queue_push(QUEUE_REFLECTIVE, path_idx);
+        }
+        case MAT_TRANSPARENT: {
+            queue_push(QUEUE_TRANSPARENT, path_idx);
+        }
+        default: {}
+    }
+}
+
+// the background is sampled from diffuse surfaces by a shadow ray, so that
+// the continued path does not take it again
+@compute
+@workgroup_size(WAVEFRONT_WORKGROUP_SIZE, 1, 1)
+fn wf_shade_diffuse(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let i = id.x + id.y * num_workgroups.x * WAVEFRONT_WORKGROUP_SIZE;
+    if i >= queues.size[QUEUE_DIFFUSE] {
+        return;
+    }
+    let path_idx = queue_item(QUEUE_DIFFUSE, i);
+
+    var hit: HitRecord;
+    let material = path_begin_shade(path_idx, &hit);
+    let path = &paths[path_idx];
+    let ray = Ray((*path).origin, (*path).direction, (*path).time);
+
+    (*path).shadow_direction = diffuse_scatter(material, ray, hit).direction;
+    queue_push(QUEUE_SHADOW, path_idx);
+
+    path_end_shade(path_idx, diffuse_scatter(material, ray, hit), PATH_SHADOWED);
+}
+
+@compute
+@workgroup_size(WAVEFRONT_WORKGROUP_SIZE, 1, 1)
+fn wf_shade_reflective(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let i = id.x + id.y * num_workgroups.x * WAVEFRONT_WORKGROUP_SIZE;
+    if i >= queues.size[QUEUE_REFLECTIVE] {
+        return;
+    }
+    let path_idx = queue_item(QUEUE_REFLECTIVE, i);
+
+    var hit: HitRecord;
+    let material = path_begin_shade(path_idx, &hit);
+    let path = &paths[path_idx];
+    let ray = Ray((*path).origin, (*path).direction, (*path).time);
+    path_end_shade(path_idx, reflective_scatter(material, ray, hit), 0u);
+}
+
+@compute
+@workgroup_size(WAVEFRONT_WORKGROUP_SIZE, 1, 1)
+fn wf_shade_transparent(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let i = id.x + id.y * num_workgroups.x * WAVEFRONT_WORKGROUP_SIZE;
+    if i >= queues.size[QUEUE_TRANSPARENT] {
+        return;
+    }
+    let path_idx = queue_item(QUEUE_TRANSPARENT, i);
+
+    var hit: HitRecord;
+    let material = path_begin_shade(path_idx, &hit);
+    let path = &paths[path_idx];
+    let ray = Ray((*path).origin, (*path).direction, (*path).time);
+    path_end_shade(path_idx, transparent_scatter(material, ray, hit), 0u);
+}
+
+// the scene is lit by the background alone, which a shadow ray reaches
+// unless it is occluded
+@compute
+@workgroup_size(WAVEFRONT_WORKGROUP_SIZE, 1, 1)
+fn wf_shadow(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    let i = id.x + id.y * num_workgroups.x * WAVEFRONT_WORKGROUP_SIZE;
+    if i >= queues.size[QUEUE_SHADOW] {
+        return;
+    }
+    let path = &paths[queue_item(QUEUE_SHADOW, i)];
+
+    let ray = Ray((*path).origin, (*path).shadow_direction, (*path).time);
+    var hit: HitRecord;
+    if !calculate_hit(ray, Interval(0.001, 1000.0), &hit) {
+        (*path).radiance += (*path).throughput * background(ray.direction);
+    }
+}
+
+// adds the finished samples to the frame buffer, and moves on to the next
+// sample of the dispatch
+@compute
+@workgroup_size(WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, 1)
+fn wf_accumulate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    // no other invocation reads the sample
+    if all(global_id.xy == vec2<u32>(0u, 0u)) {
+        queues.sample += 1u;
+    }
+
+    if any(global_id.xy >= param.dispatch_size) {
+        return;
+    }
+    let id = global_id.xy + param.dispatch_offset;
+    let path = paths[global_id.x + global_id.y * param.dispatch_size.x];
+    if (path.flags & PATH_ACTIVE) == 0u {
+        return;
+    }
+
+    let pixel = &frame[id.x + id.y * param.window_size.x];
+    let lum = luminance(path.radiance);
+    (*pixel).color += path.weight * path.radiance;
+    (*pixel).weight += path.weight;
+    (*pixel).num_sample += 1.0;
+    (*pixel).lum += lum;
+    (*pixel).moment += lum * lum;
+}
+
+// resumes the sampler of the path and returns the material it hit
+fn path_begin_shade(path_idx: u32, hit: ptr<function, HitRecord>) -> Material {
+    let path = paths[path_idx];
+    let id = vec2<u32>(path_idx % param.dispatch_size.x, path_idx / param.dispatch_size.x)
+        + param.dispatch_offset;
+    rng_resume(id + param.pixel_offset, param.image_size, stat.sample_index + queues.sample,
+        param.sampler_type, path.rng);
+
+    let point = path.origin + path.hit_t * path.direction;
+    *hit = HitRecord(point, path.hit_t, path.hit_normal, path.hit_object);
+    return materials.materials[objects.objects[path.hit_object].mat_idx];
+}
+
+// continues the path with the scattered ray
+fn path_end_shade(path_idx: u32, scattered: Ray, flags: u32) {
+    let path = &paths[path_idx];
+    let material = materials.materials[objects.objects[(*path).hit_object].mat_idx];
+
+    (*path).throughput *= material.albedo;
+    (*path).origin = scattered.origin;
+    (*path).direction = scattered.direction;
+    (*path).flags = PATH_ACTIVE | flags;
+    (*path).rng = rng_state();
+    queue_push(QUEUE_EXTEND, path_idx);
+}
+
+// the queues are as long as the paths
+fn queue_push(q: u32, path_idx: u32) {
+    let i = atomicAdd(&queues.next[q], 1u);
+    queues.items[q * param.dispatch_size.x * param.dispatch_size.y + i] = path_idx;
+}
+
+fn queue_item(q: u32, i: u32) -> u32 {
+    return queues.items[q * param.dispatch_size.x * param.dispatch_size.y + i];
+}
+
+fn div_ceil(a: u32, b: u32) -> u32 {
+    return (a + b - 1u) / b;
+}
@@ -1,11 +1,49 @@
 pub const HIT_BRUTE: u32 = 0;
 pub const HIT_BVH: u32 = 1;
+pub const HIT_RAY_QUERY: u32 = 2;
 
 pub const SHADE_FLAT: u32 = 0;
 pub const SHADE_SMOOTH: u32 = 1;
 
+pub const SAMPLER_PCG: u32 = 0;
+pub const SAMPLER_SOBOL: u32 = 1;
+pub const SAMPLER_BLUE_NOISE: u32 = 2;
+
+pub const FILTER_BOX: u32 = 0;
+pub const FILTER_GAUSSIAN: u32 = 1;
+pub const FILTER_MITCHELL: u32 = 2;
+pub const FILTER_BLACKMAN_HARRIS: u32 = 3;
+pub const FILTER_LANCZOS: u32 = 4;
+
+pub const DISPLAY_NEAREST: u32 = 0;
+pub const DISPLAY_BILINEAR: u32 = 1;
+pub const DISPLAY_BICUBIC: u32 = 2;
+
+pub const APERTURE_CIRCLE: u32 = 0;
+pub const APERTURE_POLYGON: u32 = 1;
+pub const APERTURE_MASK: u32 = 2;
+
+pub const PROJ_PERSPECTIVE: u32 = 0;
+pub const PROJ_ORTHOGRAPHIC: u32 = 1;
+pub const PROJ_FISHEYE: u32 = 2;
+pub const PROJ_EQUIRECTANGULAR: u32 = 3;
+pub const PROJ_CYLINDRICAL: u32 = 4;
+
 pub const OBJ_TRIANGLE: u32 = 0;
 pub const OBJ_SPHERE: u32 = 1;
+pub const OBJ_QUAD: u32 = 2;
+pub const OBJ_BOX: u32 = 3;
+pub const OBJ_DISK: u32 = 4;
+pub const OBJ_CYLINDER: u32 = 5;
+pub const OBJ_CONE: u32 = 6;
+pub const OBJ_SDF: u32 = 7;
+pub const OBJ_CSG: u32 = 8;
+
+pub const CSG_UNION: u32 = 0;
+pub const CSG_INTERSECTION: u32 = 1;
+pub const CSG_DIFFERENCE: u32 = 2;
+
+pub const MAX_KEYFRAME: usize = 8;
 
 pub const MAT_DIFFUSE: u32 = 0;
 pub const MAT_REFLECTIVE: u32 = 1;
@@ -13,7 +51,9 @@ pub const MAT_TRANSPARENT: u32 = 2;
 
 #[derive(Debug, Clone, encase::ShaderType)]
 pub struct Stat {
-    pub frame_counter: u32,
+    pub sample_index: u32,
+    pub num_sample: u32,
+    pub clear: u32,
 }
 
 #[derive(Debug, Clone, encase::ShaderType)]
@@ -23,6 +63,16 @@ pub struct Param {
     pub hit_algorithm: u32,
     pub shading_algorithm: u32,
     pub max_bounce: u32,
+    pub sampler_type: u32,
+    pub noise_threshold: f32,
+    pub filter_type: u32,
+    pub filter_radius: f32,
+    pub render_scale: f32,
+    pub display_filter: u32,
+    pub pixel_offset: cgmath::Vector2<u32>,
+    pub image_size: cgmath::Vector2<u32>,
+    pub dispatch_offset: cgmath::Vector2<u32>,
+    pub dispatch_size: cgmath::Vector2<u32>,
 }
 
 #[derive(Debug, Clone, encase::ShaderType)]
@@ -34,6 +84,21 @@ pub struct Camera {
     pub vx: cgmath::Vector3<f32>,
     pub vy: cgmath::Vector3<f32>,
     pub lens_radius: f32,
+    pub shutter: cgmath::Vector2<f32>,
+
+    pub aperture_shape: u32,
+    pub aperture_blades: u32,
+    pub aperture_rotation: f32,
+    pub cat_eye: f32,
+
+    pub forward: cgmath::Vector3<f32>,
+    pub projection: u32,
+    pub fov: f32,
+    pub aspect_ratio: f32,
+    pub focus_distance: f32,
+    pub exposure: f32,
+    pub window_min: cgmath::Vector2<f32>,
+    pub window_size: cgmath::Vector2<f32>,
 }
 
 #[derive(Debug, Clone, encase::ShaderType)]
@@ -53,14 +118,24 @@ pub struct BVH {
 #[derive(Debug, Clone, encase::ShaderType)]
 pub struct BVHNode {
     pub bbox: AABB,
+    pub bbox_end: AABB,
     pub left_idx: i32,
     pub right_idx: i32,
     pub object_idx: i32,
 }
 
+// parameters of a pass of the LBVH builder
 #[derive(Debug, Clone, encase::ShaderType)]
+pub struct LbvhParam {
+    pub num_object: u32,
+    pub num_block: u32,
+    pub shift: u32,
+}
+
+#[derive(Debug, Clone, Default, encase::ShaderType)]
 pub struct Objects {
-    pub num_object: encase::ArrayLength,
+    // number of top level objects, CSG operands are stored after them
+    pub num_object: u32,
 
     #[size(runtime)]
     pub objects: Vec<Object>,
@@ -70,11 +145,40 @@ pub struct Objects {
 pub struct Object {
     pub obj_type: u32,
     pub mat_idx: u32,
-    // v[0] for the center and v[1].x for the radius of sphere
+    // index of the distance function for SDF, operation for CSG
+    pub data_idx: u32,
+    // index of the motion, -1 for static objects
+    pub motion_idx: i32,
+    // triangle: v for the vertices and n for the vertex normals
+    // sphere: v[0] for the center and v[1].x for the radius
+    // quad: v[0] for the corner, v[1] and v[2] for the edges, n[0] for the normal
+    // box: v[0] for the center, v[1], v[2] and n[0] for the half extent axes
+    // disk: v[0] for the center, v[1].x for the radius, n[0] for the normal
+    // cylinder: v[0] for the base center, v[1] for the top center, v[2].x for
+    // the radius
+    // cone: v[0] for the base center, v[1] for the apex, v[2].x for the radius
+    // SDF: v[0] and v[1] for the bounding box to sphere trace in
+    // CSG: v[0] for the start of the first operand, the start of the second
+    // operand and the end of the second operand in the object list
     pub v: [cgmath::Vector3<f32>; 3],
     pub n: [cgmath::Vector3<f32>; 3],
 }
 
+#[derive(Debug, Clone, encase::ShaderType)]
+pub struct Motions {
+    pub num_motion: encase::ArrayLength,
+
+    #[size(runtime)]
+    pub motions: Vec<Motion>,
+}
+
+#[derive(Debug, Clone, encase::ShaderType)]
+pub struct Motion {
+    pub num_keyframe: u32,
+    pub times: [f32; MAX_KEYFRAME],
+    pub transforms: [cgmath::Matrix4<f32>; MAX_KEYFRAME],
+}
+
 #[derive(Debug, Clone, encase::ShaderType)]
 pub struct Materials {
     pub num_material: encase::ArrayLength,
@@ -101,20 +205,20 @@ impl Default for BVH {
     }
 }
 
-impl Default for Materials {
+impl Default for Motions {
     fn default() -> Self {
         Self {
-            num_material: encase::ArrayLength,
-            materials: Vec::new(),
+            num_motion: encase::ArrayLength,
+            motions: Vec::new(),
         }
     }
 }
 
-impl Default for Objects {
+impl Default for Materials {
     fn default() -> Self {
         Self {
-            num_object: encase::ArrayLength,
-            objects: Vec::new(),
+            num_material: encase::ArrayLength,
+            materials: Vec::new(),
         }
     }
 }
@@ -0,0 +1,121 @@
+use super::buffer::ReadBuffer;
+use crate::wgpu;
+
+// begin and end timestamps of the compute pass and the render pass
+const NUM_QUERY: u32 = 4;
+const QUERY_SIZE: u64 = NUM_QUERY as u64 * wgpu::QUERY_SIZE as u64;
+
+// GPU times of the passes by timestamp queries, the render pass is begun by
+// egui so it can only be timed from inside where that is supported
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    read: ReadBuffer,
+    // nanoseconds per tick
+    period: f32,
+    inside_passes: bool,
+    // samples per pixel of the last timed dispatch, and of the dispatch being
+    // read back
+    last_num_sample: u32,
+    read_num_sample: u32,
+}
+
+// in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpuTime {
+    pub compute: f32,
+    pub render: Option<f32>,
+    pub num_sample: u32,
+}
+
+impl GpuTimer {
+    // None if the device has no timestamp queries
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Ray Tracer Timestamp Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: NUM_QUERY,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Ray Tracer Timestamp Resolve Buffer"),
+            size: QUERY_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            read: ReadBuffer::new(device, QUERY_SIZE, Some("Ray Tracer Timestamp")),
+            period: queue.get_timestamp_period(),
+            inside_passes: device
+                .features()
+                .contains(wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES),
+            last_num_sample: 0,
+            read_num_sample: 0,
+        })
+    }
+
+    // to be encoded before the compute pass, reads back the timestamps of the
+    // previous frame
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) -> bool {
+        if self.last_num_sample == 0 {
+            return false;
+        }
+
+        encoder.resolve_query_set(&self.query_set, 0..NUM_QUERY, &self.resolve_buffer, 0);
+        let copied = self.read.copy(encoder, &self.resolve_buffer);
+        if copied {
+            self.read_num_sample = self.last_num_sample;
+        }
+        copied
+    }
+
+    pub fn compute_pass_writes(&mut self, num_sample: u32) -> wgpu::ComputePassTimestampWrites<'_> {
+        self.last_num_sample = num_sample;
+        wgpu::ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    pub fn write_render_begin(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        if self.inside_passes {
+            render_pass.write_timestamp(&self.query_set, 2);
+        }
+    }
+
+    pub fn write_render_end(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        if self.inside_passes {
+            render_pass.write_timestamp(&self.query_set, 3);
+        }
+    }
+
+    // to be called after the resolve is submitted
+    pub fn map(&self) {
+        self.read.map();
+    }
+
+    pub fn take(&mut self) -> Option<GpuTime> {
+        let data = self.read.take()?;
+        let tick = |i: usize| u64::from_ne_bytes(data[8 * i..8 * i + 8].try_into().unwrap());
+        let millis = |begin: u64, end: u64| {
+            (end > begin && begin > 0).then(|| (end - begin) as f32 * self.period / 1e6)
+        };
+
+        Some(GpuTime {
+            compute: millis(tick(0), tick(1))?,
+            render: if self.inside_passes {
+                millis(tick(2), tick(3))
+            } else {
+                None
+            },
+            num_sample: self.read_num_sample,
+        })
+    }
+}
@@ -0,0 +1,234 @@
+use std::{collections::HashMap, mem::size_of};
+
+use super::{WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, buffer, create_bind_group};
+use crate::wgpu;
+
+// mirrors of the queues in wavefront.wgsl
+const QUEUE_EXTEND: u64 = 0;
+const QUEUE_DIFFUSE: u64 = 1;
+const QUEUE_REFLECTIVE: u64 = 2;
+const QUEUE_TRANSPARENT: u64 = 3;
+const QUEUE_SHADOW: u64 = 4;
+const NUM_QUEUE: usize = 5;
+
+// size of Path
+const PATH_SIZE: usize = 28 * size_of::<u32>();
+// next and size of every queue and the sample, before the items of Queues
+const QUEUE_HEADER_SIZE: usize = (2 * NUM_QUEUE + 1) * size_of::<u32>();
+// the arguments of each queue are a vec3 padded to 16 bytes
+const DISPATCH_ARGS_STRIDE: u64 = 4 * size_of::<u32>() as u64;
+
+// path tracing split into kernels for ray generation, intersection, shading of
+// each material and shadow rays, which pass the paths on through queues so
+// that the invocations of a dispatch run the same code
+pub struct Wavefront {
+    generate_pipeline: wgpu::ComputePipeline,
+    queues_pipeline: wgpu::ComputePipeline,
+    intersect_pipeline: wgpu::ComputePipeline,
+    // in the order of the material queues
+    shade_pipelines: [wgpu::ComputePipeline; 3],
+    shadow_pipeline: wgpu::ComputePipeline,
+    accumulate_pipeline: wgpu::ComputePipeline,
+
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    queues_bind_group_layout: wgpu::BindGroupLayout,
+    queues_bind_group: wgpu::BindGroup,
+
+    // one path per pixel of the dispatch, allocated only once needed
+    queue_storage: buffer::StorageBuffer<false>,
+    path_storage: buffer::StorageBuffer<false>,
+    dispatch_args_storage: buffer::StorageBuffer<false>,
+    num_path: u64,
+}
+
+impl Wavefront {
+    // the shader module and the bind group layouts of the first three groups
+    // are shared with the megakernel
+    pub fn new(
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+        shared_layouts: [&wgpu::BindGroupLayout; 3],
+        constants: &HashMap<String, f64>,
+    ) -> Self {
+        let (queue_storage, path_storage) = create_buffers(device, 1);
+        let dispatch_args_storage = buffer::StorageBuffer::<false>::new_indirect(
+            device,
+            NUM_QUEUE * DISPATCH_ARGS_STRIDE as usize,
+            Some("Ray Tracer Wavefront Dispatch Arguments"),
+        );
+
+        let (bind_group_layout, bind_group) = create_bind_group(
+            device,
+            &[&queue_storage, &path_storage],
+            wgpu::ShaderStages::COMPUTE,
+            Some("Ray Tracer Wavefront"),
+        );
+        let (queues_bind_group_layout, queues_bind_group) = create_bind_group(
+            device,
+            &[&queue_storage, &dispatch_args_storage],
+            wgpu::ShaderStages::COMPUTE,
+            Some("Ray Tracer Wavefront Queues"),
+        );
+
+        let pipeline = |entry_point: &str, layout: &wgpu::BindGroupLayout| {
+            let [uniform, storage, texture] = shared_layouts;
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&format!(
+                    "Ray Tracer Wavefront {entry_point} Pipeline Layout"
+                )),
+                bind_group_layouts: &[uniform, storage, texture, layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(&format!("Ray Tracer Wavefront {entry_point} Pipeline")),
+                layout: Some(&pipeline_layout),
+                module,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants,
+                    ..Default::default()
+                },
+                cache: None,
+            })
+        };
+
+        Self {
+            generate_pipeline: pipeline("wf_generate", &bind_group_layout),
+            queues_pipeline: pipeline("wf_queues", &queues_bind_group_layout),
+            intersect_pipeline: pipeline("wf_intersect", &bind_group_layout),
+            shade_pipelines: [
+                pipeline("wf_shade_diffuse", &bind_group_layout),
+                pipeline("wf_shade_reflective", &bind_group_layout),
+                pipeline("wf_shade_transparent", &bind_group_layout),
+            ],
+            shadow_pipeline: pipeline("wf_shadow", &bind_group_layout),
+            accumulate_pipeline: pipeline("wf_accumulate", &bind_group_layout),
+
+            bind_group_layout,
+            bind_group,
+            queues_bind_group_layout,
+            queues_bind_group,
+
+            queue_storage,
+            path_storage,
+            dispatch_args_storage,
+            num_path: 1,
+        }
+    }
+
+    // reallocates the paths and the queues, which fails if they exceed what
+    // the device can bind
+    pub fn resize(&mut self, device: &wgpu::Device, num_path: u64) -> Result<(), String> {
+        let num_path = num_path.max(1);
+        if num_path == self.num_path {
+            return Ok(());
+        }
+
+        let limits = device.limits();
+        let max_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        let size = num_path * PATH_SIZE as u64;
+        if size > max_size {
+            return Err(format!(
+                "Wavefront needs {} MB for the paths, beyond the {} MB the device can bind",
+                size >> 20,
+                max_size >> 20,
+            ));
+        }
+
+        (self.queue_storage, self.path_storage) = create_buffers(device, num_path);
+        self.bind_group = super::create_bind_group_with_layout(
+            device,
+            &self.bind_group_layout,
+            &[&self.queue_storage, &self.path_storage],
+            Some("Ray Tracer Wavefront"),
+        );
+        self.queues_bind_group = super::create_bind_group_with_layout(
+            device,
+            &self.queues_bind_group_layout,
+            &[&self.queue_storage, &self.dispatch_args_storage],
+            Some("Ray Tracer Wavefront Queues"),
+        );
+        self.num_path = num_path;
+        Ok(())
+    }
+
+    // empties the queues and starts from the first sample, before each
+    // dispatch
+    pub fn clear(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.queue_storage.buffer, 0, &[0; QUEUE_HEADER_SIZE]);
+    }
+
+    // to be encoded with the first three bind groups set, the queues are
+    // sized on the GPU so the kernels over them are dispatched indirectly
+    pub fn dispatch(
+        &self,
+        compute_pass: &mut wgpu::ComputePass<'_>,
+        dispatch_size: cgmath::Vector2<u32>,
+        num_sample: u32,
+        max_bounce: u32,
+    ) {
+        let num_workgroup_x = dispatch_size.x.div_ceil(WORKGROUP_SIZE_X);
+        let num_workgroup_y = dispatch_size.y.div_ceil(WORKGROUP_SIZE_Y);
+        compute_pass.set_bind_group(3, &self.bind_group, &[]);
+
+        for _ in 0..num_sample {
+            compute_pass.set_pipeline(&self.generate_pipeline);
+            compute_pass.dispatch_workgroups(num_workgroup_x, num_workgroup_y, 1);
+            self.swap_queues(compute_pass);
+
+            for _ in 0..max_bounce {
+                self.dispatch_queue(compute_pass, &self.intersect_pipeline, QUEUE_EXTEND);
+                self.swap_queues(compute_pass);
+
+                let material_queues = [QUEUE_DIFFUSE, QUEUE_REFLECTIVE, QUEUE_TRANSPARENT];
+                for (pipeline, queue) in self.shade_pipelines.iter().zip(material_queues) {
+                    self.dispatch_queue(compute_pass, pipeline, queue);
+                }
+                self.swap_queues(compute_pass);
+
+                self.dispatch_queue(compute_pass, &self.shadow_pipeline, QUEUE_SHADOW);
+            }
+
+            compute_pass.set_pipeline(&self.accumulate_pipeline);
+            compute_pass.dispatch_workgroups(num_workgroup_x, num_workgroup_y, 1);
+        }
+    }
+
+    fn swap_queues(&self, compute_pass: &mut wgpu::ComputePass<'_>) {
+        compute_pass.set_pipeline(&self.queues_pipeline);
+        compute_pass.set_bind_group(3, &self.queues_bind_group, &[]);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+        compute_pass.set_bind_group(3, &self.bind_group, &[]);
+    }
+
+    fn dispatch_queue(
+        &self,
+        compute_pass: &mut wgpu::ComputePass<'_>,
+        pipeline: &wgpu::ComputePipeline,
+        queue: u64,
+    ) {
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.dispatch_workgroups_indirect(
+            &self.dispatch_args_storage.buffer,
+            queue * DISPATCH_ARGS_STRIDE,
+        );
+    }
+}
+
+fn create_buffers(
+    device: &wgpu::Device,
+    num_path: u64,
+) -> (buffer::StorageBuffer<false>, buffer::StorageBuffer<false>) {
+    let queue_storage = buffer::StorageBuffer::<false>::new_with_size(
+        device,
+        QUEUE_HEADER_SIZE + NUM_QUEUE * num_path as usize * size_of::<u32>(),
+        Some("Ray Tracer Wavefront Queues"),
+    );
+    let path_storage = buffer::StorageBuffer::<false>::new_with_size(
+        device,
+        num_path as usize * PATH_SIZE,
+        Some("Ray Tracer Wavefront Paths"),
+    );
+    (queue_storage, path_storage)
+}